@@ -0,0 +1,136 @@
+//! Compares plain spawn/despawn of bullets against the `BulletPool`.
+//!
+//! Runs the bullet systems without a window for a fixed number of frames,
+//! spawning `BULLETS_PER_FRAME` bullets every frame and releasing each one
+//! `BULLET_LIFETIME_FRAMES` frames later. Lyon's `ShapePlugin` needs the
+//! render app, so new shapes are tessellated by a stand-in system instead.
+//!
+//! Run with `cargo bench --bench bullet_pool`.
+
+use bevy::asset::HandleId;
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy::sprite::Mesh2dHandle;
+use bevy_prototype_lyon::prelude::tess::{
+    geometry_builder::simple_builder, math::Point, FillTessellator, VertexBuffers,
+};
+use bevy_prototype_lyon::prelude::*;
+use gameing::bullet::BulletPlugin;
+use gameing::bullet_pool::{BulletPool, BulletStyle};
+use gameing::config::GameConfig;
+use gameing::game::GameState;
+use gameing::timestep::{FixedTime, FixedTimestepPlugin};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const FRAMES: u32 = 600;
+const WARMUP_FRAMES: u32 = 60;
+const BULLETS_PER_FRAME: usize = 100;
+const BULLET_LIFETIME_FRAMES: usize = 60;
+
+#[derive(Default)]
+struct LiveBullets(VecDeque<Vec<Entity>>);
+
+fn spawn_and_release(
+    mut commands: Commands,
+    mut pool: ResMut<BulletPool>,
+    mut live: ResMut<LiveBullets>,
+    config: Res<GameConfig>,
+) {
+    let style = BulletStyle {
+        radius: config.bullet_radius,
+        color: Color::ORANGE,
+    };
+    let mut spawned = Vec::with_capacity(BULLETS_PER_FRAME);
+    for i in 0..BULLETS_PER_FRAME {
+        let angle = i as f32 / BULLETS_PER_FRAME as f32 * std::f32::consts::TAU;
+        spawned.push(
+            pool.spawn(
+                &mut commands,
+                Transform::default(),
+                angle,
+                config.bullet_speed,
+                style,
+            )
+            .id(),
+        );
+    }
+    live.0.push_back(spawned);
+
+    if live.0.len() > BULLET_LIFETIME_FRAMES {
+        for entity in live.0.pop_front().unwrap() {
+            pool.release(&mut commands, entity);
+        }
+    }
+}
+
+/// Stand-in for lyon's meshing system: tessellates every new `Path` and
+/// points its `Mesh2dHandle` at the (not stored) result.
+fn tessellate_new_shapes(
+    mut tessellator: Local<FillTessellator>,
+    mut q_shapes: Query<(&Path, &mut Mesh2dHandle), Changed<Path>>,
+) {
+    for (path, mut mesh2d) in q_shapes.iter_mut() {
+        let mut buffers: VertexBuffers<Point, u16> = VertexBuffers::new();
+        tessellator
+            .tessellate_path(
+                &path.0,
+                &FillOptions::default(),
+                &mut simple_builder(&mut buffers),
+            )
+            .unwrap();
+
+        let positions: Vec<[f32; 3]> = buffers
+            .vertices
+            .iter()
+            .map(|point| [point.x, point.y, 0.])
+            .collect();
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_indices(Some(Indices::U16(buffers.indices)));
+        std::hint::black_box(mesh);
+        mesh2d.0 = Handle::weak(HandleId::random::<Mesh>());
+    }
+}
+
+fn run(pooled: bool) -> Duration {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_state(GameState::Playing)
+        .insert_resource(FixedTime::default().with_lockstep())
+        .add_plugin(FixedTimestepPlugin)
+        .init_resource::<GameConfig>()
+        .add_plugin(BulletPlugin)
+        .init_resource::<LiveBullets>()
+        .add_system(spawn_and_release)
+        .add_system_to_stage(CoreStage::PostUpdate, tessellate_new_shapes);
+    app.world.get_resource_mut::<BulletPool>().unwrap().enabled = pooled;
+
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+    }
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        app.update();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    println!(
+        "{} frames, {} bullets per frame, {} frames of lifetime",
+        FRAMES, BULLETS_PER_FRAME, BULLET_LIFETIME_FRAMES
+    );
+    for (name, pooled) in [("spawn/despawn", false), ("pooled", true)] {
+        let elapsed = run(pooled);
+        let frame_time = elapsed / FRAMES;
+        let spawn_rate = (FRAMES as usize * BULLETS_PER_FRAME) as f64 / elapsed.as_secs_f64();
+        println!(
+            "{:>14}: {:>8.3} ms/frame, {:>10.0} bullets/s",
+            name,
+            frame_time.as_secs_f64() * 1000.,
+            spawn_rate
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use crate::timestep::{FixedTime, IgnoresTimeScale, TimeScale};
 use bevy::prelude::*;
 
 #[derive(Component, Clone)]
@@ -18,6 +19,17 @@ impl Cooldown {
         self.timer.tick(delta);
     }
 
+    /// Keeps the time already waited, a running cooldown may finish right away.
+    pub fn set_duration(&mut self, seconds: f32) {
+        self.timer.set_duration(Duration::from_secs_f32(seconds));
+    }
+
+    /// Makes the cooldown ready right away.
+    pub fn finish(&mut self) {
+        let left = self.timer.duration().saturating_sub(self.timer.elapsed());
+        self.timer.tick(left);
+    }
+
     pub fn start(&mut self) {
         self.timer.reset()
     }
@@ -31,11 +43,15 @@ impl Cooldown {
     }
 }
 
-pub(crate) fn tick_cooldowns(mut query: Query<&mut Cooldown>, time: Res<Time>) {
-    for mut cooldown in query.iter_mut() {
+pub(crate) fn tick_cooldowns(
+    mut query: Query<(&mut Cooldown, Option<&IgnoresTimeScale>)>,
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+) {
+    for (mut cooldown, ignores) in query.iter_mut() {
         // Extra check here avoids change-detection false positives
         if !cooldown.finished() {
-            cooldown.tick(time.delta());
+            cooldown.tick(time_scale.delta_for(&time, ignores));
         }
     }
 }
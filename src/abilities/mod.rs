@@ -1,16 +1,35 @@
 mod cooldown;
 
-use crate::game::GameState;
+use crate::timestep::FixedUpdateStage;
+use bevy::ecs::query::{FilterFetch, WorldQuery};
 use bevy::prelude::*;
 pub use cooldown::*;
 pub struct AbilitiesPlugin;
 
 impl Plugin for AbilitiesPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_update(GameState::Playing).with_system(tick_cooldowns));
+        app.add_system_set_to_stage(
+            FixedUpdateStage,
+            SystemSet::new().with_system(tick_cooldowns.before("input")),
+        );
     }
 }
 
 /// Marker component for Ability entities
 #[derive(Component, Clone, Copy)]
 pub struct Ability;
+
+/// The first of `children` that `query` matches, e.g. the one ability of a
+/// kind among the player's.
+pub fn find_ability<Q: WorldQuery, F: WorldQuery>(
+    children: &Children,
+    query: &Query<Q, F>,
+) -> Option<Entity>
+where
+    F::Fetch: FilterFetch,
+{
+    children
+        .iter()
+        .copied()
+        .find(|&child| query.get(child).is_ok())
+}
@@ -1,9 +1,12 @@
+use crate::arena::Cursor;
 use crate::direction::Direction;
-use crate::game::GameState;
 use crate::player::Player;
+use crate::timestep::{FixedTime, FixedUpdateStage};
 use crate::utils::*;
+use bevy::input::mouse::MouseWheel;
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::*;
+use std::collections::HashMap;
 
 pub struct ActionsPlugin;
 impl Plugin for ActionsPlugin {
@@ -11,13 +14,30 @@ impl Plugin for ActionsPlugin {
         app.add_event::<MovementEvent>()
             .add_event::<ShootEvent>()
             .add_event::<DashEvent>()
+            .add_event::<ShieldEvent>()
+            .add_event::<BombEvent>()
+            .add_event::<BulletTimeEvent>()
+            .add_event::<SwitchWeaponEvent>()
+            .add_event::<ActionReleased>()
             .init_resource::<ActionsMap>()
+            .init_resource::<WeaponScroll>()
             .add_plugin(InputManagerPlugin::<Actions>::default())
-            .add_system_set(
-                SystemSet::on_update(GameState::Playing)
+            .add_system_to_stage(CoreStage::PreUpdate, count_weapon_scroll)
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new()
+                    .with_system(track_action_holds.label("input").label("action_holds"))
                     .with_system(handle_movement_input.label("input"))
-                    .with_system(handle_shoot_input.label("input"))
-                    .with_system(handle_dash_input.label("input")),
+                    .with_system(handle_shoot_input.label("input").after("action_holds"))
+                    .with_system(handle_dash_input.label("input"))
+                    .with_system(handle_shield_input.label("input").after("action_holds"))
+                    .with_system(handle_bomb_input.label("input").after("action_holds"))
+                    .with_system(
+                        handle_bullet_time_input
+                            .label("input")
+                            .after("action_holds"),
+                    )
+                    .with_system(handle_weapon_input.label("input").after("action_holds")),
             );
     }
 }
@@ -26,12 +46,63 @@ pub struct MovementEvent {
     pub direction: Direction,
 }
 
+/// Sent every fixed step the shoot action is held, and once more on the
+/// step it is let go.
 pub struct ShootEvent {
     pub angle: f32,
+    /// Seconds the action has been held so far
+    pub held: f32,
+    pub released: bool,
+}
+
+/// Sent on the fixed step an action is let go.
+pub struct ActionReleased {
+    pub action: Actions,
+    /// Seconds the action was held
+    pub held: f32,
+}
+
+/// How long each held action of the player has been held.
+///
+/// NOTE: counted in fixed steps, not taken from the `ActionState`, so replays
+/// see the same holds
+#[derive(Component, Default)]
+pub struct ActionHolds(HashMap<Actions, f32>);
+
+impl ActionHolds {
+    /// Seconds the action has been held, `None` while it is not.
+    pub fn held(&self, action: Actions) -> Option<f32> {
+        self.0.get(&action).copied()
+    }
+
+    /// Whether the action got pressed on this fixed step.
+    pub fn just_pressed(&self, action: Actions) -> bool {
+        self.held(action) == Some(0.)
+    }
 }
 
 pub struct DashEvent;
 
+pub struct ShieldEvent;
+
+pub struct BombEvent;
+
+/// Turns bullet time on, or off again while it is on.
+pub struct BulletTimeEvent;
+
+/// Switch to the weapon `offset` places further.
+pub struct SwitchWeaponEvent {
+    pub offset: isize,
+}
+
+/// Mouse wheel notches since the last fixed step, up for the next weapon and
+/// down for the previous one.
+///
+/// NOTE: most frames run no fixed step at high frame rates, so the notches
+/// add up until one does
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WeaponScroll(pub i16);
+
 pub struct ActionsMap {
     pub input_map: InputMap<Actions>,
 }
@@ -54,6 +125,11 @@ pub enum Actions {
     // Abilities
     Shoot,
     Dash,
+    Shield,
+    Bomb,
+    BulletTime,
+    NextWeapon,
+    PreviousWeapon,
 }
 
 impl Actions {
@@ -85,6 +161,11 @@ impl Actions {
         // Abilities
         input_map.insert(Shoot, MouseButton::Left);
         input_map.insert(Dash, KeyCode::Space);
+        input_map.insert(Shield, MouseButton::Right);
+        input_map.insert(Bomb, KeyCode::F);
+        input_map.insert(BulletTime, KeyCode::LShift);
+        input_map.insert(NextWeapon, KeyCode::E);
+        input_map.insert(PreviousWeapon, KeyCode::Q);
 
         input_map
     }
@@ -113,9 +194,34 @@ fn handle_movement_input(
     }
 }
 
+fn track_action_holds(
+    time: Res<FixedTime>,
+    mut query: Query<(&ActionState<Actions>, &mut ActionHolds), With<Player>>,
+    mut event_writer: EventWriter<ActionReleased>,
+) {
+    for (action_state, mut holds) in query.iter_mut() {
+        for action in Actions::iter() {
+            match (action_state.pressed(&action), holds.held(action)) {
+                (true, Some(held)) => {
+                    holds.0.insert(action, held + time.delta_seconds());
+                }
+                (true, None) => {
+                    holds.0.insert(action, 0.);
+                }
+                (false, Some(held)) => {
+                    holds.0.remove(&action);
+                    event_writer.send(ActionReleased { action, held });
+                }
+                (false, None) => {}
+            }
+        }
+    }
+}
+
 fn handle_shoot_input(
-    windows: Res<Windows>,
-    query: Query<(&ActionState<Actions>, &Transform), With<Player>>,
+    cursor: Res<Cursor>,
+    query: Query<(&ActionHolds, &Transform), With<Player>>,
+    mut releases: EventReader<ActionReleased>,
     mut event_writer: EventWriter<ShootEvent>,
 ) {
     let player = query.get_single();
@@ -123,12 +229,20 @@ fn handle_shoot_input(
         eprintln!("{:?}", err);
         return;
     }
-    let (action_state, player_transform) = player.unwrap();
-    if action_state.pressed(&Actions::Shoot) {
-        let window = windows.get_primary().unwrap();
-
-        if let Some(angle) = get_angle_between_transform_and_cursor(window, player_transform) {
-            event_writer.send(ShootEvent { angle });
+    let (holds, player_transform) = player.unwrap();
+    let released = releases
+        .iter()
+        .filter(|event| event.action == Actions::Shoot)
+        .map(|event| (event.held, true));
+    let held = holds.held(Actions::Shoot).map(|held| (held, false));
+    let angle = get_angle_between_transform_and_cursor(&cursor, player_transform);
+    for (held, released) in released.chain(held) {
+        if let Some(angle) = angle {
+            event_writer.send(ShootEvent {
+                angle,
+                held,
+                released,
+            });
         }
     }
 }
@@ -147,3 +261,76 @@ fn handle_dash_input(
         event_writer.send(DashEvent);
     }
 }
+
+/// NOTE: raises the shield once per press, however long it is held
+fn handle_shield_input(
+    query: Query<&ActionHolds, With<Player>>,
+    mut event_writer: EventWriter<ShieldEvent>,
+) {
+    if let Ok(holds) = query.get_single() {
+        if holds.just_pressed(Actions::Shield) {
+            event_writer.send(ShieldEvent);
+        }
+    }
+}
+
+/// NOTE: one bomb per press, holding it down does not use up the stock
+fn handle_bomb_input(
+    query: Query<&ActionHolds, With<Player>>,
+    mut event_writer: EventWriter<BombEvent>,
+) {
+    if let Ok(holds) = query.get_single() {
+        if holds.just_pressed(Actions::Bomb) {
+            event_writer.send(BombEvent);
+        }
+    }
+}
+
+fn handle_bullet_time_input(
+    query: Query<&ActionHolds, With<Player>>,
+    mut event_writer: EventWriter<BulletTimeEvent>,
+) {
+    if let Ok(holds) = query.get_single() {
+        if holds.just_pressed(Actions::BulletTime) {
+            event_writer.send(BulletTimeEvent);
+        }
+    }
+}
+
+/// NOTE: switches once per press, however many fixed steps it is held for
+fn handle_weapon_input(
+    mut scroll: ResMut<WeaponScroll>,
+    query: Query<&ActionHolds, With<Player>>,
+    mut event_writer: EventWriter<SwitchWeaponEvent>,
+) {
+    let holds = match query.get_single() {
+        Ok(holds) => holds,
+        Err(_) => return,
+    };
+    for (action, offset) in [(Actions::NextWeapon, 1), (Actions::PreviousWeapon, -1)] {
+        if holds.just_pressed(action) {
+            event_writer.send(SwitchWeaponEvent { offset });
+        }
+    }
+    if scroll.0 != 0 {
+        event_writer.send(SwitchWeaponEvent {
+            offset: scroll.0 as isize,
+        });
+        scroll.0 = 0;
+    }
+}
+
+/// The mouse wheel has no place in the `InputMap`, every notch counts
+/// towards the `WeaponScroll` instead.
+fn count_weapon_scroll(
+    mut events: EventReader<MouseWheel>,
+    mut scroll: ResMut<WeaponScroll>,
+    query: Query<(), With<Player>>,
+) {
+    for event in events.iter() {
+        // NOTE: outside of a run there is no weapon to switch
+        if event.y != 0. && !query.is_empty() {
+            scroll.0 += event.y.signum() as i16;
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use bevy::prelude::*;
+
+/// Keeps `Arena` and `Cursor` in sync with the primary window.
+///
+/// Gameplay systems only read those two resources, so a headless app can
+/// drive them by hand instead.
+pub struct WindowArenaPlugin;
+
+impl Plugin for WindowArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Arena>()
+            .init_resource::<Cursor>()
+            .add_system_to_stage(CoreStage::PreUpdate, sync_arena_with_window);
+    }
+}
+
+/// Size of the playing field, centered on the origin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arena {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self {
+            width: 1280.,
+            height: 720.,
+        }
+    }
+}
+
+impl Arena {
+    pub fn half_extents(&self) -> Vec2 {
+        Vec2::new(self.width / 2., self.height / 2.)
+    }
+}
+
+/// Cursor position in world coordinates, `None` when it is not over the arena.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cursor {
+    pub position: Option<Vec2>,
+}
+
+fn sync_arena_with_window(
+    windows: Res<Windows>,
+    mut arena: ResMut<Arena>,
+    mut cursor: ResMut<Cursor>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    // get the size of the window
+    let size = Vec2::new(window.width(), window.height());
+    let new_arena = Arena {
+        width: size.x,
+        height: size.y,
+    };
+    if *arena != new_arena {
+        *arena = new_arena;
+    }
+
+    // the default orthographic projection is in pixels from the center;
+    // just undo the translation
+    let position = window.cursor_position().map(|pos| pos - size / 2.0);
+    if cursor.position != position {
+        cursor.position = position;
+    }
+}
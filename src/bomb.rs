@@ -0,0 +1,270 @@
+use crate::abilities::{find_ability, Cooldown};
+use crate::actions::BombEvent;
+use crate::bullet::BulletCancelled;
+use crate::bullet_pool::BulletPool;
+use crate::collide::{Collideable, Destroyed, Health, Invulnerable};
+use crate::enemy::{Enemy, EnemyBullet};
+use crate::game::GameState;
+use crate::game_abilities::{BombAbility, BombStock};
+use crate::player::Player;
+use crate::timestep::{FixedTime, FixedUpdateStage};
+use bevy::app::{Events, ManualEventReader};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+const BLAST_SECONDS: f32 = 0.4;
+
+/// The player's panic button: a bomb clears the enemy bullets around the
+/// player and hurts the enemies there. Kills slowly refill the stock.
+pub struct BombPlugin;
+
+impl Plugin for BombPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_bomb_hud))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(update_bomb_hud))
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new()
+                    .with_system(
+                        // NOTE: after the collisions, to know what they destroyed already
+                        use_bomb.label("bomb").after("input").after("collision"),
+                    )
+                    .with_system(refill_bombs.after("bomb"))
+                    .with_system(fade_bomb_blasts),
+            );
+    }
+}
+
+#[derive(Component)]
+struct BombBlast {
+    timer: Timer,
+}
+
+#[derive(Component)]
+struct BombHud;
+
+#[derive(Component)]
+struct BombIcon(u32);
+
+type BombingPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static Children,
+        Option<&'static Invulnerable>,
+    ),
+    With<Player>,
+>;
+
+type BombedEnemyQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static Collideable,
+        &'static mut Health,
+    ),
+    (With<Enemy>, Without<Invulnerable>),
+>;
+
+/// What a bomb goes off on.
+#[derive(SystemParam)]
+struct BombTargets<'w, 's> {
+    enemy_bullets: Query<'w, 's, (Entity, &'static Transform), With<EnemyBullet>>,
+    enemies: BombedEnemyQuery<'w, 's>,
+    // NOTE: reads what the collisions destroyed, and adds to it
+    destroyed_reader: Local<'s, ManualEventReader<Destroyed>>,
+    destroyed_events: ResMut<'w, Events<Destroyed>>,
+}
+
+fn use_bomb(
+    mut commands: Commands,
+    mut pool: ResMut<BulletPool>,
+    mut events: EventReader<BombEvent>,
+    mut cancelled_events: EventWriter<BulletCancelled>,
+    q_player: BombingPlayerQuery,
+    mut q_ability: Query<(&BombAbility, &mut Cooldown, &mut BombStock)>,
+    mut targets: BombTargets,
+) {
+    let destroyed: Vec<Entity> = targets
+        .destroyed_reader
+        .iter(&targets.destroyed_events)
+        .map(|event| event.entity)
+        .collect();
+    if events.iter().count() == 0 {
+        return;
+    }
+    let (player, player_transform, children, invulnerable) = match q_player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let ability =
+        find_ability(children, &q_ability).and_then(|ability| q_ability.get_mut(ability).ok());
+    let (bomb, mut cd, mut stock) = match ability {
+        Some(ability) => ability,
+        None => return,
+    };
+    if !cd.finished() || !stock.take() {
+        return;
+    }
+    cd.start();
+
+    let center = player_transform.translation.truncate();
+    for (bullet, transform) in targets.enemy_bullets.iter() {
+        let position = transform.translation.truncate();
+        if pool.is_released(bullet) || position.distance(center) > bomb.radius {
+            continue;
+        }
+        pool.release(&mut commands, bullet);
+        cancelled_events.send(BulletCancelled { position, bonus: 0 });
+    }
+    for (enemy, transform, collideable, mut health) in targets.enemies.iter_mut() {
+        let position = transform.translation.truncate();
+        if destroyed.contains(&enemy)
+            || position.distance(center) > bomb.radius + collideable.radius
+        {
+            continue;
+        }
+        health.current = health.current.saturating_sub(bomb.damage);
+        if health.current == 0 {
+            targets.destroyed_events.send(Destroyed {
+                entity: enemy,
+                position,
+            });
+            commands.entity(enemy).despawn_recursive();
+        }
+    }
+
+    Invulnerable::extend_to(&mut commands, player, invulnerable, bomb.grace_time);
+    spawn_bomb_blast(&mut commands, center, bomb.radius);
+}
+
+fn spawn_bomb_blast(commands: &mut Commands, center: Vec2, radius: f32) {
+    let shape = shapes::Circle {
+        radius,
+        center: Vec2::ZERO,
+    };
+    commands
+        .spawn_bundle(GeometryBuilder::build_as(
+            &shape,
+            DrawMode::Outlined {
+                fill_mode: FillMode::color(Color::rgba(1., 1., 1., 0.3)),
+                outline_mode: StrokeMode::new(Color::WHITE, 4.0),
+            },
+            Transform::from_translation(center.extend(8.)),
+        ))
+        .insert(BombBlast {
+            timer: Timer::from_seconds(BLAST_SECONDS, false),
+        });
+}
+
+fn fade_bomb_blasts(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut query: Query<(Entity, &mut Transform, &mut BombBlast)>,
+) {
+    for (entity, mut transform, mut blast) in query.iter_mut() {
+        if blast.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        // NOTE: grows out to the bomb's reach
+        transform.scale = Vec3::splat(blast.timer.percent());
+    }
+}
+
+fn refill_bombs(
+    mut events: EventReader<Destroyed>,
+    q_enemy: Query<(), With<Enemy>>,
+    q_player: Query<&Children, With<Player>>,
+    mut q_stock: Query<&mut BombStock>,
+) {
+    let children = match q_player.get_single() {
+        Ok(children) => children,
+        Err(_) => return,
+    };
+    let stock = find_ability(children, &q_stock).and_then(|stock| q_stock.get_mut(stock).ok());
+    let mut stock = match stock {
+        Some(stock) => stock,
+        None => return,
+    };
+    for event in events.iter() {
+        if q_enemy.get(event.entity).is_ok() {
+            stock.count_kill();
+        }
+    }
+}
+
+fn spawn_bomb_hud(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(BombHud);
+}
+
+/// One square per bomb left, out of as many as the stock holds.
+fn update_bomb_hud(
+    mut commands: Commands,
+    q_player: Query<&Children, With<Player>>,
+    q_stock: Query<&BombStock, Changed<BombStock>>,
+    q_hud: Query<Entity, With<BombHud>>,
+    mut q_icons: Query<(Entity, &BombIcon, &mut Style)>,
+) {
+    let stock = q_player
+        .get_single()
+        .ok()
+        .and_then(|children| children.iter().find_map(|&child| q_stock.get(child).ok()));
+    let (stock, hud) = match (stock, q_hud.get_single()) {
+        (Some(stock), Ok(hud)) => (stock, hud),
+        _ => return,
+    };
+    let display = |icon: u32| {
+        if icon < stock.bombs {
+            Display::Flex
+        } else {
+            Display::None
+        }
+    };
+
+    let mut icons = 0;
+    for (entity, icon, mut style) in q_icons.iter_mut() {
+        if icon.0 >= stock.max {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        icons += 1;
+        style.display = display(icon.0);
+    }
+    // NOTE: the stock size comes from the config, which can change during the run
+    commands.entity(hud).with_children(|parent| {
+        for i in icons..stock.max {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(14.0), Val::Px(14.0)),
+                        margin: Rect::all(Val::Px(2.0)),
+                        display: display(i),
+                        ..Default::default()
+                    },
+                    color: Color::WHITE.into(),
+                    ..Default::default()
+                })
+                .insert(BombIcon(i));
+        }
+    });
+}
@@ -0,0 +1,446 @@
+use crate::arena::Arena;
+use crate::bullet::{BulletBehaviour, BulletLimits};
+use crate::bullet_pool::BulletPool;
+use crate::collide::{Collideable, Destroyed, Health, Invulnerable};
+use crate::difficulty::DifficultyScaling;
+use crate::enemy::{spawn_abilities, Enemy, EnemyBullet, EnemySpawner};
+use crate::enemy_ai::{AiProfile, EnemyAi};
+use crate::enemy_archetype::{EnemyKind, BASE_SPEED};
+use crate::game::{GameState, Speed};
+use crate::game_abilities::{BulletEmitter, EmittedBehaviour, DEFAULT_BULLET_SPEED};
+use crate::timestep::{FixedTime, FixedUpdateStage, IgnoresTimeScale, TimeScale};
+use crate::waves::{WaveProgress, WaveScript};
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use std::f32::consts::PI;
+
+const INTRO_SECONDS: f32 = 2.;
+const PHASE_CHANGE_SECONDS: f32 = 1.;
+
+/// Brings in the boss once the last wave is cleared, switches its phases
+/// as it loses health and shows its health bar. Defeating it ends the run.
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BossEncounter>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(reset_boss_encounter),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(update_boss_health_bar),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new()
+                    // NOTE: before the last wave is spawned, not before it is cleared
+                    .with_system(spawn_boss_when_cleared.after("collision").before("waves"))
+                    .with_system(play_boss_intro.label("boss_intro").after("collision"))
+                    .with_system(change_boss_phase.after("boss_intro").after("bomb"))
+                    .with_system(play_boss_defeat.after("collision").after("bomb"))
+                    .with_system(fade_boss_wrecks),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BossEncounter {
+    /// Shows up once the last wave is cleared
+    #[default]
+    Waiting,
+    Fighting,
+    Defeated,
+}
+
+/// Movement and abilities of the boss while its health is above the next
+/// phase's threshold.
+#[derive(Clone)]
+pub struct BossPhase {
+    /// Starts once the boss is down to this fraction of its health
+    pub below_health: f32,
+    pub ai: AiProfile,
+    pub speed: f32,
+    pub shoot_cooldown: Option<f32>,
+    pub emitter: Option<(BulletEmitter, f32)>,
+}
+
+impl BossPhase {
+    /// The phase with its stats scaled for the difficulty, like an `EnemyArchetype`.
+    fn scaled(&self, difficulty: &DifficultyScaling) -> Self {
+        let mut phase = self.clone();
+        phase.speed *= difficulty.enemy_speed;
+        phase.shoot_cooldown = self
+            .shoot_cooldown
+            .map(|seconds| difficulty.cooldown(seconds));
+        if let Some((emitter, seconds)) = &mut phase.emitter {
+            emitter.bullet_speed *= difficulty.bullet_speed;
+            *seconds = difficulty.cooldown(*seconds);
+        }
+        phase
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Boss {
+    /// NOTE: ordered by `below_health`, highest first
+    pub phases: Vec<BossPhase>,
+    /// Current phase, `None` during the intro
+    pub phase: Option<usize>,
+}
+
+impl Boss {
+    pub fn new(phases: Vec<BossPhase>) -> Self {
+        Self {
+            phases,
+            phase: None,
+        }
+    }
+
+    /// Phase the boss should be in at the given health.
+    fn phase_at(&self, health: &Health) -> usize {
+        self.phases
+            .iter()
+            .rposition(|phase| health.fraction() <= phase.below_health)
+            .unwrap_or(0)
+    }
+}
+
+/// Slowly keeps its distance, then closes in, then strafes, then goes for the
+/// player, with weaving, then orbiting, then speeding up, then homing bullets.
+pub fn boss_phases() -> Vec<BossPhase> {
+    let limits = BulletLimits {
+        max_lifetime: None,
+        max_distance: Some(1000.),
+    };
+    vec![
+        BossPhase {
+            below_health: 1.,
+            ai: AiProfile {
+                preferred_distance: 450.,
+                distance_tolerance: 100.,
+                dodges: false,
+                flee_threat: usize::MAX,
+                ..AiProfile::SHOOTER
+            },
+            speed: BASE_SPEED * 0.5,
+            shoot_cooldown: Some(0.8),
+            emitter: Some((
+                BulletEmitter::spiral(10, PI / 3.)
+                    .with_volleys(3, 0.2, PI / 10.)
+                    .with_bullets(
+                        DEFAULT_BULLET_SPEED,
+                        Color::ORANGE_RED,
+                        BulletBehaviour::SineWave {
+                            amplitude: 20.,
+                            frequency: 1.5,
+                        },
+                    )
+                    .with_limits(limits),
+                2.5,
+            )),
+        },
+        BossPhase {
+            below_health: 0.8,
+            ai: AiProfile {
+                preferred_distance: 200.,
+                distance_tolerance: 60.,
+                dodges: false,
+                flee_threat: usize::MAX,
+                ..AiProfile::SHOOTER
+            },
+            speed: BASE_SPEED,
+            shoot_cooldown: Some(0.8),
+            emitter: Some((
+                BulletEmitter::radial(8)
+                    .with_bullets(
+                        DEFAULT_BULLET_SPEED,
+                        Color::ORANGE_RED,
+                        // NOTE: a ring around the boss that has to be broken through
+                        EmittedBehaviour::OrbitShooter {
+                            radius: 120.,
+                            angular_speed: PI / 2.,
+                        },
+                    )
+                    .with_limits(limits),
+                3.,
+            )),
+        },
+        BossPhase {
+            below_health: 0.6,
+            ai: AiProfile {
+                sight_range: f32::MAX,
+                preferred_distance: 300.,
+                distance_tolerance: 80.,
+                dodges: false,
+                flee_threat: usize::MAX,
+                strafe_switch_time: 1.5,
+            },
+            speed: BASE_SPEED * 1.5,
+            shoot_cooldown: Some(0.5),
+            emitter: Some((
+                BulletEmitter::radial(16)
+                    .with_volleys(2, 0.3, PI / 16.)
+                    .with_bullets(
+                        DEFAULT_BULLET_SPEED * 0.4,
+                        Color::ORANGE_RED,
+                        BulletBehaviour::Accelerating {
+                            acceleration: 200.,
+                            min_speed: 0.,
+                            max_speed: DEFAULT_BULLET_SPEED * 1.5,
+                        },
+                    )
+                    .with_limits(limits),
+                2.,
+            )),
+        },
+        BossPhase {
+            below_health: 0.25,
+            ai: AiProfile {
+                preferred_distance: 150.,
+                distance_tolerance: 50.,
+                ..AiProfile::SWARMER
+            },
+            speed: BASE_SPEED * 2.,
+            shoot_cooldown: None,
+            emitter: Some((
+                BulletEmitter::aimed_spread(5, PI / 4.)
+                    .with_bullets(
+                        DEFAULT_BULLET_SPEED,
+                        Color::CRIMSON,
+                        BulletBehaviour::Homing { turn_rate: 1.5 },
+                    )
+                    .with_limits(limits),
+                1.,
+            )),
+        },
+    ]
+}
+
+/// Grows in while invulnerable before the first phase starts.
+#[derive(Component)]
+struct BossIntro {
+    timer: Timer,
+}
+
+/// Shockwave left behind by a defeated boss, the run is won once it is gone.
+#[derive(Component)]
+struct BossWreck {
+    timer: Timer,
+}
+
+#[derive(Component)]
+struct BossHealthBar;
+
+#[derive(Component)]
+struct BossHealthFill;
+
+fn reset_boss_encounter(mut encounter: ResMut<BossEncounter>) {
+    *encounter = BossEncounter::Waiting;
+}
+
+fn spawn_boss_when_cleared(
+    mut spawner: EnemySpawner,
+    mut encounter: ResMut<BossEncounter>,
+    arena: Res<Arena>,
+    script: Res<WaveScript>,
+    progress: Res<WaveProgress>,
+    q_enemy: Query<(), With<Enemy>>,
+) {
+    if *encounter != BossEncounter::Waiting || !progress.finished(&script) || !q_enemy.is_empty() {
+        return;
+    }
+    let position = Vec2::new(arena.half_extents().x - 200., 0.);
+    let boss = spawner.spawn(EnemyKind::Boss, position);
+    spawner
+        .commands
+        .entity(boss)
+        .insert(Boss::new(boss_phases()))
+        .insert(BossIntro {
+            timer: Timer::from_seconds(INTRO_SECONDS, false),
+        })
+        .insert(Invulnerable::new(INTRO_SECONDS))
+        .insert(Transform {
+            translation: position.extend(10.),
+            scale: Vec3::ZERO,
+            ..Default::default()
+        });
+    *encounter = BossEncounter::Fighting;
+}
+
+fn play_boss_intro(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<(
+        Entity,
+        &mut Transform,
+        &mut BossIntro,
+        Option<&IgnoresTimeScale>,
+    )>,
+) {
+    for (entity, mut transform, mut intro, ignores) in query.iter_mut() {
+        intro.timer.tick(time_scale.delta_for(&time, ignores));
+        transform.scale = Vec3::splat(intro.timer.percent());
+        if intro.timer.finished() {
+            commands.entity(entity).remove::<BossIntro>();
+        }
+    }
+}
+
+type BossQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Boss,
+        &'static Health,
+        &'static mut EnemyAi,
+        &'static mut Speed,
+        Option<&'static Children>,
+    ),
+    Without<BossIntro>,
+>;
+
+/// Swaps the boss' movement and abilities for the next phase, with a short
+/// invulnerability so the change can be seen.
+fn change_boss_phase(
+    mut commands: Commands,
+    difficulty: Res<DifficultyScaling>,
+    mut query: BossQuery,
+) {
+    for (entity, mut boss, health, mut ai, mut speed, children) in query.iter_mut() {
+        // NOTE: destroyed during this step, and already on its way out
+        if health.current == 0 {
+            continue;
+        }
+        let phase = boss.phase_at(health);
+        if boss.phase == Some(phase) {
+            continue;
+        }
+        let first = boss.phase.is_none();
+        boss.phase = Some(phase);
+        let phase = boss.phases[phase].scaled(&difficulty);
+
+        *ai = EnemyAi::new(phase.ai);
+        speed.0 = phase.speed;
+        for &child in children.into_iter().flat_map(|children| children.iter()) {
+            commands.entity(child).despawn_recursive();
+        }
+        let abilities = spawn_abilities(&mut commands, phase.shoot_cooldown, phase.emitter);
+        commands.entity(entity).push_children(&abilities);
+        if !first {
+            commands
+                .entity(entity)
+                .insert(Invulnerable::new(PHASE_CHANGE_SECONDS));
+        }
+    }
+}
+
+/// Clears the screen of enemy bullets and leaves a shockwave behind.
+fn play_boss_defeat(
+    mut commands: Commands,
+    mut pool: ResMut<BulletPool>,
+    mut encounter: ResMut<BossEncounter>,
+    mut events: EventReader<Destroyed>,
+    q_boss: Query<&Collideable, With<Boss>>,
+    q_enemy_bullets: Query<Entity, With<EnemyBullet>>,
+) {
+    for event in events.iter() {
+        let collidable = match q_boss.get(event.entity) {
+            Ok(collidable) => collidable,
+            Err(_) => continue,
+        };
+        *encounter = BossEncounter::Defeated;
+        for bullet in q_enemy_bullets.iter() {
+            pool.release(&mut commands, bullet);
+        }
+
+        let shape = shapes::Circle {
+            radius: collidable.radius,
+            center: Vec2::ZERO,
+        };
+        commands
+            .spawn_bundle(GeometryBuilder::build_as(
+                &shape,
+                DrawMode::Stroke(StrokeMode::new(Color::CRIMSON, 4.0)),
+                Transform::from_translation(event.position.extend(5.)),
+            ))
+            .insert(BossWreck {
+                timer: Timer::from_seconds(1.5, false),
+            });
+    }
+}
+
+fn fade_boss_wrecks(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut state: ResMut<State<GameState>>,
+    mut query: Query<(Entity, &mut Transform, &mut BossWreck)>,
+) {
+    for (entity, mut transform, mut wreck) in query.iter_mut() {
+        if wreck.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            // NOTE: `BossEncounter::Defeated` tells the game over screen it was a win
+            state.overwrite_set(GameState::GameOver).unwrap();
+            continue;
+        }
+        transform.scale = Vec3::splat(1. + 5. * wreck.timer.percent());
+    }
+}
+
+/// Shows the bar at the top of the screen while a boss is around.
+fn update_boss_health_bar(
+    mut commands: Commands,
+    q_boss: Query<&Health, With<Boss>>,
+    q_bar: Query<Entity, With<BossHealthBar>>,
+    mut q_fill: Query<&mut Style, With<BossHealthFill>>,
+) {
+    let health = match q_boss.get_single() {
+        Ok(health) => health,
+        Err(_) => {
+            for bar in q_bar.iter() {
+                commands.entity(bar).despawn_recursive();
+            }
+            return;
+        }
+    };
+    if q_bar.is_empty() {
+        spawn_boss_health_bar(&mut commands);
+        return;
+    }
+    for mut style in q_fill.iter_mut() {
+        style.size.width = Val::Percent(100. * health.fraction());
+    }
+}
+
+fn spawn_boss_health_bar(commands: &mut Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(20.0),
+                    top: Val::Px(16.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(60.0), Val::Px(14.0)),
+                padding: Rect::all(Val::Px(2.0)),
+                ..Default::default()
+            },
+            color: Color::rgb(0.15, 0.15, 0.15).into(),
+            ..Default::default()
+        })
+        .insert(BossHealthBar)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                        ..Default::default()
+                    },
+                    color: Color::CRIMSON.into(),
+                    ..Default::default()
+                })
+                .insert(BossHealthFill);
+        });
+}
@@ -1,17 +1,58 @@
+use crate::bullet_pool::*;
+use crate::collide::Collider;
+use crate::config::GameConfig;
+use crate::enemy::{Enemy, EnemyBullet};
 use crate::game::GameState;
+use crate::movement::Velocity;
+use crate::player::{Player, PlayerBullet};
+use crate::timestep::{FixedTime, FixedUpdateStage, TimeScale};
 use bevy::prelude::*;
 use bevy_prototype_lyon::entity::ShapeBundle;
 use bevy_prototype_lyon::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::PI;
 
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(update_bullets)
-                .with_system(handle_bullet_leave_window_events),
-        );
+        app.add_event::<BulletCancelled>()
+            .init_resource::<BulletPool>()
+            .add_system_to_stage(CoreStage::First, recycle_released_bullets)
+            .add_system_to_stage(CoreStage::PostUpdate, register_bullet_meshes)
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(clear_bullet_pool))
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new()
+                    .with_system(
+                        steer_homing_bullets::<PlayerBullet, Enemy>
+                            .label("steering")
+                            .after("movement"),
+                    )
+                    .with_system(
+                        steer_homing_bullets::<EnemyBullet, Player>
+                            .after("steering")
+                            .before("bullet_movement"),
+                    )
+                    .with_system(update_bullets.label("bullet_movement").after("steering"))
+                    .with_system(
+                        despawn_expired_bullets
+                            .after("bullet_movement")
+                            .before("velocity"),
+                    )
+                    .with_system(
+                        cancel_enemy_bullets
+                            .label("bullet_cancelling")
+                            .after("velocity"),
+                    )
+                    .with_system(spawn_cancel_sparks.after("bullet_cancelling"))
+                    .with_system(update_sparks)
+                    .with_system(
+                        handle_bullet_leave_window_events
+                            .after("velocity")
+                            .before("bullet_cancelling"),
+                    ),
+            );
     }
 }
 
@@ -22,36 +63,135 @@ pub struct Bullet;
 pub struct BulletAttributes {
     pub angle: f32,
     pub speed: f32,
+    pub elapsed: f32,   // NOTE: seconds since the bullet was fired
+    pub travelled: f32, // NOTE: points covered since the bullet was fired
+}
+
+/// Optional limits after which a bullet despawns on its own.
+#[derive(Component, Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BulletLimits {
+    pub max_lifetime: Option<f32>, // NOTE: seconds
+    pub max_distance: Option<f32>,
+}
+
+/// Lets a bullet pass through `remaining` targets before despawning.
+///
+/// Targets already pierced are remembered so a bullet only hits each of them
+/// once while passing through.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Pierce {
+    pub remaining: u32,
+    pub hit: Vec<Entity>,
+}
+
+impl Pierce {
+    pub fn new(remaining: u32) -> Self {
+        Self {
+            remaining,
+            hit: Vec::new(),
+        }
+    }
+}
+
+/// How a bullet moves once it has been fired.
+///
+/// `Straight` is the default; other behaviours can be inserted over it after
+/// spawning a `BulletBundle`.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub enum BulletBehaviour {
+    #[default]
+    Straight,
+    /// Turns towards the nearest target, at most `turn_rate` radians per second.
+    Homing { turn_rate: f32 },
+    /// Weaves sideways around its heading.
+    SineWave { amplitude: f32, frequency: f32 },
+    /// Changes speed over time (negative `acceleration` to decelerate).
+    Accelerating {
+        acceleration: f32,
+        min_speed: f32,
+        max_speed: f32,
+    },
+    /// Circles around `center` (usually the shooter) at `radius` points.
+    Orbit {
+        center: Entity,
+        radius: f32,
+        angular_speed: f32,
+    },
+}
+
+/// Opt-in for player bullets: erases the enemy bullets it touches.
+///
+/// A bullet with `remaining: Some(n)` is spent after erasing `n` enemy
+/// bullets, a heavy shot with `remaining: None` keeps going.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CancelsBullets {
+    pub remaining: Option<u32>,
+    pub bonus: u32, // NOTE: score awarded per erased bullet
+}
+
+/// Sent every time a player bullet erases an enemy bullet, so scoring can
+/// hook into it.
+pub struct BulletCancelled {
+    pub position: Vec2,
+    pub bonus: u32,
+}
+
+#[derive(Component)]
+struct Spark {
+    timer: Timer,
 }
 
 #[derive(Bundle)]
 pub struct BulletBundle {
     pub bullet: Bullet,
     pub bullet_attributes: BulletAttributes,
+    pub behaviour: BulletBehaviour,
+    pub limits: BulletLimits,
+    pub velocity: Velocity,
     #[bundle]
     pub shape: ShapeBundle,
 }
 
-pub(crate) fn create_bullet_bundle(
-    mut transform: Transform,
+impl BulletAttributes {
+    pub fn new(angle: f32, speed: f32) -> Self {
+        Self {
+            angle,
+            speed,
+            elapsed: 0.,
+            travelled: 0.,
+        }
+    }
+}
+
+/// Where a bullet fired from `transform` towards `angle` appears, `offset`
+/// in front of the shooter.
+pub fn muzzle_transform(mut transform: Transform, angle: f32, offset: f32) -> Transform {
+    transform.translation += offset * heading(angle).extend(0.);
+    transform
+}
+
+pub fn create_bullet_bundle(
+    transform: Transform,
     angle: f32,
     speed: f32,
-    color: Color,
+    style: BulletStyle,
 ) -> BulletBundle {
     let shape = shapes::Circle {
-        radius: 8.,
+        radius: style.radius,
         center: Vec2::ZERO,
     };
 
-    transform.translation += 30. * (Vec3::X * angle.cos() + Vec3::Y * angle.sin());
-
     BulletBundle {
         bullet: Bullet,
-        bullet_attributes: BulletAttributes { angle, speed },
+        bullet_attributes: BulletAttributes::new(angle, speed),
+        behaviour: BulletBehaviour::default(),
+        limits: BulletLimits::default(),
+        velocity: Velocity(speed * heading(angle)),
         shape: GeometryBuilder::build_as(
             &shape,
             DrawMode::Outlined {
-                fill_mode: FillMode::color(color),
+                fill_mode: FillMode::color(style.color),
                 outline_mode: StrokeMode::new(Color::BLACK, 0.0),
             },
             transform,
@@ -59,24 +199,230 @@ pub(crate) fn create_bullet_bundle(
     }
 }
 
+pub fn heading(angle: f32) -> Vec2 {
+    Vec2::new(angle.cos(), angle.sin())
+}
+
+/// Wraps an angle into the `(-PI, PI]` range.
+fn wrap_angle(angle: f32) -> f32 {
+    let wrapped = (angle + PI).rem_euclid(2. * PI) - PI;
+    if wrapped == -PI {
+        PI
+    } else {
+        wrapped
+    }
+}
+
+fn steer_homing_bullets<B: Component, T: Component>(
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut q_bullet: Query<(&Transform, &mut BulletAttributes, &BulletBehaviour), With<B>>,
+    q_target: Query<&Transform, (With<T>, Without<Bullet>)>,
+) {
+    for (transform, mut attributes, behaviour) in q_bullet.iter_mut() {
+        if let BulletBehaviour::Homing { turn_rate } = *behaviour {
+            let position = transform.translation.truncate();
+            let closest_target = q_target
+                .iter()
+                .map(|target| target.translation.truncate())
+                .min_by(|a, b| {
+                    a.distance_squared(position)
+                        .partial_cmp(&b.distance_squared(position))
+                        .unwrap()
+                });
+            if let Some(target) = closest_target {
+                let wanted_angle = Vec2::X.angle_between(target - position);
+                if wanted_angle.is_nan() {
+                    continue;
+                }
+                let max_turn = turn_rate * time_scale.delta_seconds(&time);
+                let turn = wrap_angle(wanted_angle - attributes.angle).clamp(-max_turn, max_turn);
+                attributes.angle = wrap_angle(attributes.angle + turn);
+            }
+        }
+    }
+}
+
 fn update_bullets(
-    mut q_bullet: Query<(&mut Transform, &BulletAttributes), With<Bullet>>,
-    time: Res<Time>,
+    mut q_bullet: Query<
+        (
+            &Transform,
+            &mut Velocity,
+            &mut BulletAttributes,
+            &BulletBehaviour,
+        ),
+        With<Bullet>,
+    >,
+    q_center: Query<&Transform, Without<Bullet>>,
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+) {
+    let delta = time_scale.delta_seconds(&time);
+    if delta <= 0. {
+        return;
+    }
+    for (transform, mut velocity, mut attributes, behaviour) in q_bullet.iter_mut() {
+        let elapsed = attributes.elapsed;
+        attributes.elapsed += delta;
+        velocity.0 = match *behaviour {
+            BulletBehaviour::Straight | BulletBehaviour::Homing { .. } => {
+                attributes.speed * heading(attributes.angle)
+            }
+            BulletBehaviour::SineWave {
+                amplitude,
+                frequency,
+            } => {
+                let omega = 2. * PI * frequency;
+                let lateral =
+                    amplitude * ((omega * (elapsed + delta)).sin() - (omega * elapsed).sin());
+                attributes.speed * heading(attributes.angle)
+                    + lateral / delta * heading(attributes.angle + PI / 2.)
+            }
+            BulletBehaviour::Accelerating {
+                acceleration,
+                min_speed,
+                max_speed,
+            } => {
+                attributes.speed =
+                    (attributes.speed + acceleration * delta).clamp(min_speed, max_speed);
+                attributes.speed * heading(attributes.angle)
+            }
+            BulletBehaviour::Orbit {
+                center,
+                radius,
+                angular_speed,
+            } => {
+                if let Ok(center) = q_center.get(center) {
+                    let relative = (transform.translation - center.translation).truncate();
+                    let phase = Vec2::X.angle_between(relative);
+                    let phase = if phase.is_nan() {
+                        attributes.angle
+                    } else {
+                        phase
+                    };
+                    let phase = phase + angular_speed * delta;
+                    let target = center.translation.truncate() + radius * heading(phase);
+                    // NOTE: keep the angle tangent to the orbit so dodging still works
+                    attributes.angle = wrap_angle(phase + angular_speed.signum() * PI / 2.);
+                    (target - transform.translation.truncate()) / delta
+                } else {
+                    // The shooter is gone, fly off along the tangent
+                    attributes.speed * heading(attributes.angle)
+                }
+            }
+        };
+        attributes.travelled += velocity.0.length() * delta;
+    }
+}
+
+fn despawn_expired_bullets(
+    mut commands: Commands,
+    mut pool: ResMut<BulletPool>,
+    q_bullets: Query<(Entity, &BulletAttributes, &BulletLimits), With<Bullet>>,
 ) {
-    for (mut transform, attributes) in q_bullet.iter_mut() {
-        transform.translation += attributes.speed
-            * time.delta_seconds()
-            * (Vec3::X * attributes.angle.cos() + Vec3::Y * attributes.angle.sin());
+    for (ent, attributes, limits) in q_bullets.iter() {
+        let too_old = limits
+            .max_lifetime
+            .is_some_and(|lifetime| attributes.elapsed >= lifetime);
+        let too_far = limits
+            .max_distance
+            .is_some_and(|distance| attributes.travelled >= distance);
+        if too_old || too_far {
+            pool.release(&mut commands, ent);
+        }
     }
 }
 
 fn handle_bullet_leave_window_events(
     mut commands: Commands,
+    mut pool: ResMut<BulletPool>,
     q_bullets: Query<(Entity, &Transform), With<Bullet>>,
 ) {
     for (ent, bullet) in q_bullets.iter() {
         if bullet.translation.x.abs() > 2000. || bullet.translation.y.abs() > 1500. {
+            pool.release(&mut commands, ent);
+        }
+    }
+}
+
+fn cancel_enemy_bullets(
+    mut commands: Commands,
+    mut pool: ResMut<BulletPool>,
+    mut events: EventWriter<BulletCancelled>,
+    mut q_cancellers: Query<
+        (Entity, &Transform, &Collider, &mut CancelsBullets),
+        With<PlayerBullet>,
+    >,
+    q_enemy_bullets: Query<(Entity, &Transform, &Collider), With<EnemyBullet>>,
+) {
+    for (canceller, canceller_transform, canceller_collider, mut cancels) in q_cancellers.iter_mut()
+    {
+        if pool.is_released(canceller) {
+            continue;
+        }
+        for (enemy_bullet, transform, collider) in q_enemy_bullets.iter() {
+            if cancels.remaining == Some(0) {
+                break;
+            }
+            if pool.is_released(enemy_bullet) {
+                continue;
+            }
+            let distance = canceller_transform
+                .translation
+                .distance(transform.translation);
+            if distance < canceller_collider.radius + collider.radius {
+                pool.release(&mut commands, enemy_bullet);
+                events.send(BulletCancelled {
+                    position: transform.translation.truncate(),
+                    bonus: cancels.bonus,
+                });
+                if let Some(remaining) = cancels.remaining.as_mut() {
+                    *remaining -= 1;
+                }
+            }
+        }
+        if cancels.remaining == Some(0) {
+            pool.release(&mut commands, canceller);
+        }
+    }
+}
+
+fn spawn_cancel_sparks(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut events: EventReader<BulletCancelled>,
+) {
+    let shape = shapes::RegularPolygon {
+        sides: 4,
+        feature: shapes::RegularPolygonFeature::Radius(config.bullet_radius),
+        ..shapes::RegularPolygon::default()
+    };
+    for event in events.iter() {
+        commands
+            .spawn_bundle(GeometryBuilder::build_as(
+                &shape,
+                DrawMode::Stroke(StrokeMode::new(Color::YELLOW, 2.0)),
+                Transform::from_translation(event.position.extend(5.)),
+            ))
+            .insert(Spark {
+                timer: Timer::from_seconds(0.2, false),
+            });
+    }
+}
+
+fn update_sparks(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut q_sparks: Query<(Entity, &mut Transform, &mut Spark)>,
+) {
+    for (ent, mut transform, mut spark) in q_sparks.iter_mut() {
+        spark.timer.tick(time.delta());
+        if spark.timer.finished() {
             commands.entity(ent).despawn();
+            continue;
         }
+        let scale = 1. + 2. * spark.timer.percent();
+        transform.scale = Vec3::splat(scale);
+        transform.rotate(Quat::from_rotation_z(8. * time.delta_seconds()));
     }
 }
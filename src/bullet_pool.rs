@@ -0,0 +1,207 @@
+use crate::bullet::*;
+use crate::collide::{Collider, Damage};
+use crate::enemy::EnemyBullet;
+use crate::movement::Velocity;
+use crate::player::PlayerBullet;
+use crate::timestep::Interpolated;
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use bevy::sprite::Mesh2dHandle;
+use bevy_prototype_lyon::render::Shape;
+use std::collections::HashSet;
+
+/// Everything that makes two bullets look the same, so they can share a mesh.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct BulletStyle {
+    pub radius: f32,
+    pub color: Color,
+}
+
+/// Reuses bullet entities instead of spawning and despawning one per shot.
+///
+/// Released bullets are hidden and stripped of their gameplay components, and
+/// handed out again by `spawn` for the next shot with the same `BulletStyle`.
+/// Once lyon has meshed the first bullet of a style, the mesh handle is shared
+/// by every new bullet of that style so nothing gets tessellated twice.
+///
+/// With `enabled` set to false the pool falls back to plain spawn/despawn.
+pub struct BulletPool {
+    pub enabled: bool,
+    meshes: Vec<(BulletStyle, Mesh2dHandle)>,
+    free: Vec<(BulletStyle, Vec<Entity>)>,
+    // NOTE: released this frame; their removal commands may not have run yet
+    pending: Vec<Entity>,
+    released: HashSet<Entity>,
+}
+
+impl Default for BulletPool {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            meshes: Vec::new(),
+            free: Vec::new(),
+            pending: Vec::new(),
+            released: HashSet::new(),
+        }
+    }
+}
+
+/// Every gameplay component a pooled bullet can be given, on top of its shape
+/// and `BulletStyle`. Released bullets are stripped of all of them, so a
+/// component inserted on bullets anywhere has to be listed here.
+pub type PooledBullet = (
+    Bullet,
+    BulletAttributes,
+    BulletBehaviour,
+    BulletLimits,
+    Velocity,
+    Pierce,
+    Damage,
+    CancelsBullets,
+    Collider,
+    PlayerBullet,
+    EnemyBullet,
+    Interpolated,
+);
+
+#[derive(Bundle)]
+struct SharedMeshBundle {
+    shape: Shape,
+    mesh2d: Mesh2dHandle,
+    transform: Transform,
+    global_transform: GlobalTransform,
+    visibility: Visibility,
+    computed_visibility: ComputedVisibility,
+}
+
+impl BulletPool {
+    /// Drop-in replacement for `commands.spawn_bundle(create_bullet_bundle(..))`.
+    pub fn spawn<'w, 's, 'a>(
+        &mut self,
+        commands: &'a mut Commands<'w, 's>,
+        transform: Transform,
+        angle: f32,
+        speed: f32,
+        style: BulletStyle,
+    ) -> EntityCommands<'w, 's, 'a> {
+        if !self.enabled {
+            return commands.spawn_bundle(create_bullet_bundle(transform, angle, speed, style));
+        }
+
+        let bullet = (
+            Bullet,
+            BulletAttributes::new(angle, speed),
+            BulletBehaviour::default(),
+            BulletLimits::default(),
+            Velocity(speed * Vec2::new(angle.cos(), angle.sin())),
+        );
+
+        let free = self
+            .free
+            .iter_mut()
+            .find(|(free_style, _)| *free_style == style)
+            .and_then(|(_, entities)| entities.pop());
+        if let Some(entity) = free {
+            self.released.remove(&entity);
+            let mut entity = commands.entity(entity);
+            entity
+                .insert_bundle(bullet)
+                .insert_bundle((transform, Visibility { is_visible: true }));
+            return entity;
+        }
+
+        let mesh = self
+            .meshes
+            .iter()
+            .find(|(mesh_style, _)| *mesh_style == style)
+            .map(|(_, mesh)| mesh.clone());
+        let mut entity = match mesh {
+            Some(mesh2d) => commands.spawn_bundle(SharedMeshBundle {
+                shape: Shape,
+                mesh2d,
+                transform,
+                global_transform: GlobalTransform::default(),
+                visibility: Visibility::default(),
+                computed_visibility: ComputedVisibility::default(),
+            }),
+            None => {
+                commands.spawn_bundle(create_bullet_bundle(transform, angle, speed, style).shape)
+            }
+        };
+        entity.insert_bundle(bullet).insert(style);
+        entity
+    }
+
+    /// Drop-in replacement for despawning a bullet. Safe to call several
+    /// times for the same bullet within a frame.
+    pub fn release(&mut self, commands: &mut Commands, entity: Entity) {
+        if !self.enabled {
+            commands.entity(entity).despawn();
+            return;
+        }
+        if !self.released.insert(entity) {
+            return;
+        }
+        commands
+            .entity(entity)
+            .remove_bundle::<PooledBullet>()
+            .insert(Visibility { is_visible: false });
+        self.pending.push(entity);
+    }
+
+    pub fn is_released(&self, entity: Entity) -> bool {
+        self.released.contains(&entity)
+    }
+
+    fn clear(&mut self) {
+        self.free.clear();
+        self.pending.clear();
+        self.released.clear();
+    }
+}
+
+/// Makes last frame's released bullets available again, now that their
+/// removal commands have been applied.
+pub(crate) fn recycle_released_bullets(mut pool: ResMut<BulletPool>, q_style: Query<&BulletStyle>) {
+    let pending = std::mem::take(&mut pool.pending);
+    for entity in pending {
+        let style = match q_style.get(entity) {
+            Ok(style) => *style,
+            Err(_) => {
+                pool.released.remove(&entity);
+                continue;
+            }
+        };
+        match pool
+            .free
+            .iter_mut()
+            .find(|(free_style, _)| *free_style == style)
+        {
+            Some((_, entities)) => entities.push(entity),
+            None => pool.free.push((style, vec![entity])),
+        }
+    }
+}
+
+pub(crate) fn register_bullet_meshes(
+    mut pool: ResMut<BulletPool>,
+    q_meshes: Query<(&BulletStyle, &Mesh2dHandle), Changed<Mesh2dHandle>>,
+) {
+    for (style, mesh) in q_meshes.iter() {
+        if mesh.0 == Handle::default() {
+            continue;
+        }
+        if !pool
+            .meshes
+            .iter()
+            .any(|(mesh_style, _)| mesh_style == style)
+        {
+            pool.meshes.push((*style, mesh.clone()));
+        }
+    }
+}
+
+/// Pooled entities are despawned with everything else when leaving the game.
+pub(crate) fn clear_bullet_pool(mut pool: ResMut<BulletPool>) {
+    pool.clear();
+}
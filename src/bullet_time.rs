@@ -0,0 +1,118 @@
+use crate::abilities::find_ability;
+use crate::actions::BulletTimeEvent;
+use crate::game::GameState;
+use crate::game_abilities::{BulletTimeAbility, BulletTimeMeter};
+use crate::player::Player;
+use crate::timestep::{FixedTime, FixedUpdateStage, TimeScale};
+use bevy::prelude::*;
+
+/// Lets the player slow the world down for as long as the bullet time meter
+/// lasts, and shows the meter.
+pub struct BulletTimePlugin;
+
+impl Plugin for BulletTimePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing).with_system(spawn_bullet_time_hud),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(update_bullet_time_hud),
+        )
+        .add_system_to_stage(
+            FixedUpdateStage,
+            // NOTE: every system of a step sees the same `TimeScale`, the input
+            // of a step takes effect on the next one
+            update_bullet_time.exclusive_system().at_start(),
+        );
+    }
+}
+
+#[derive(Component)]
+struct BulletTimeBarFill;
+
+fn update_bullet_time(
+    time: Res<FixedTime>,
+    mut time_scale: ResMut<TimeScale>,
+    mut events: EventReader<BulletTimeEvent>,
+    q_player: Query<&Children, With<Player>>,
+    mut q_ability: Query<(&BulletTimeAbility, &mut BulletTimeMeter)>,
+) {
+    let toggled = events.iter().count() % 2 == 1;
+    let children = match q_player.get_single() {
+        Ok(children) => children,
+        Err(_) => return,
+    };
+    let ability =
+        find_ability(children, &q_ability).and_then(|ability| q_ability.get_mut(ability).ok());
+    let (bullet_time, mut meter) = match ability {
+        Some(ability) => ability,
+        None => return,
+    };
+    if toggled {
+        meter.active = !meter.active && meter.seconds > 0.;
+    }
+    // NOTE: the meter runs on real time, not on the slowed down one
+    meter.tick(time.delta_seconds(), bullet_time.refill_rate);
+
+    let scale = if meter.active {
+        bullet_time.time_scale
+    } else {
+        1.
+    };
+    if time_scale.0 != scale {
+        time_scale.0 = scale;
+    }
+}
+
+fn spawn_bullet_time_hud(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(32.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(120.0), Val::Px(10.0)),
+                padding: Rect::all(Val::Px(2.0)),
+                ..Default::default()
+            },
+            color: Color::rgb(0.15, 0.15, 0.15).into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                        ..Default::default()
+                    },
+                    color: Color::TEAL.into(),
+                    ..Default::default()
+                })
+                .insert(BulletTimeBarFill);
+        });
+}
+
+/// Brighter while bullet time is on.
+fn update_bullet_time_hud(
+    q_player: Query<&Children, With<Player>>,
+    q_meter: Query<&BulletTimeMeter>,
+    mut q_fill: Query<(&mut Style, &mut UiColor), With<BulletTimeBarFill>>,
+) {
+    let meter = q_player
+        .get_single()
+        .ok()
+        .and_then(|children| children.iter().find_map(|&child| q_meter.get(child).ok()));
+    if let Some(meter) = meter {
+        for (mut style, mut color) in q_fill.iter_mut() {
+            style.size.width = Val::Percent(100. * meter.fraction());
+            color.0 = if meter.active {
+                Color::CYAN
+            } else {
+                Color::TEAL
+            };
+        }
+    }
+}
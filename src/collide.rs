@@ -1,16 +1,35 @@
-use crate::game::GameState;
+use crate::arena::Arena;
+use crate::bullet::{Bullet, Pierce};
+use crate::bullet_pool::BulletPool;
+use crate::config::GameConfig;
+use crate::enemy::{Enemy, EnemyBullet};
+use crate::movement::Velocity;
+use crate::player::PlayerBullet;
+use crate::timestep::{FixedTime, FixedUpdateStage, IgnoresTimeScale, TimeScale};
 use bevy::prelude::*;
 
 pub struct CollidePlugin;
 
 impl Plugin for CollidePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<EntityLeaveWindow>().add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(collide_system)
-                .with_system(detect_entity_leaving)
-                .with_system(handle_leave_window_events.after("movement")),
-        );
+        app.add_event::<EntityLeaveWindow>()
+            .add_event::<Destroyed>()
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new()
+                    .with_system(collide_system.label("collision").after("bullet_cancelling"))
+                    .with_system(tick_invulnerability.label("invulnerability"))
+                    .with_system(
+                        detect_entity_leaving
+                            .label("leave_window")
+                            .after("velocity"),
+                    )
+                    .with_system(
+                        handle_leave_window_events
+                            .after("leave_window")
+                            .before("bullet_cancelling"),
+                    ),
+            );
     }
 }
 
@@ -24,6 +43,80 @@ pub struct Collider {
     pub radius: f32,
 }
 
+/// Stretches a `Collider` into a segment, from its position to `reach` away
+/// from it. It hits whatever is within its radius of any point along it.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Beam {
+    pub reach: Vec2,
+}
+
+impl Beam {
+    /// How far `point` is from the segment that starts at `start`.
+    pub fn distance(&self, start: Vec2, point: Vec2) -> f32 {
+        let along = (point - start).dot(self.reach) / self.reach.length_squared().max(f32::EPSILON);
+        point.distance(start + along.clamp(0., 1.) * self.reach)
+    }
+}
+
+/// Hits a `Collideable` takes before it is destroyed. Without one, the first
+/// hit destroys it.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.current as f32 / self.max as f32
+    }
+}
+
+/// Health a `Collider` takes off what it hits. Without one, it takes one.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Damage(pub u32);
+
+/// Sent when a collision destroys an entity, before it is despawned.
+pub struct Destroyed {
+    pub entity: Entity,
+    pub position: Vec2,
+}
+
+/// Makes a `Collideable` ignore every `Collider` until the timer runs out.
+#[derive(Component)]
+pub struct Invulnerable {
+    timer: Timer,
+}
+
+impl Invulnerable {
+    pub fn new(seconds: f32) -> Self {
+        Self {
+            timer: Timer::from_seconds(seconds, false),
+        }
+    }
+
+    pub fn remaining(&self) -> f32 {
+        self.timer.duration().as_secs_f32() - self.timer.elapsed_secs()
+    }
+
+    /// Makes `entity` invulnerable for `seconds`, without cutting a longer
+    /// `current` invulnerability short.
+    pub fn extend_to(
+        commands: &mut Commands,
+        entity: Entity,
+        current: Option<&Invulnerable>,
+        seconds: f32,
+    ) {
+        if current.is_none_or(|invulnerable| invulnerable.remaining() < seconds) {
+            commands.entity(entity).insert(Invulnerable::new(seconds));
+        }
+    }
+}
+
 pub struct EntityLeaveWindow {
     pub entity: Entity,
     pub last_x: f32,
@@ -32,36 +125,163 @@ pub struct EntityLeaveWindow {
 #[derive(Component)]
 pub struct DetectLeave;
 
+type CollidableQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static Collideable,
+        Option<&'static mut Health>,
+        Option<&'static Enemy>,
+    ),
+    Without<Invulnerable>,
+>;
+
+type ColliderQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static Collider,
+        Option<&'static Beam>,
+        Option<&'static Damage>,
+        Option<&'static mut Pierce>,
+        Option<&'static Bullet>,
+        Option<&'static PlayerBullet>,
+        // NOTE: an `Or` here would also filter out everything on the player's side
+        Option<&'static Enemy>,
+        Option<&'static EnemyBullet>,
+    ),
+>;
+
 fn collide_system(
     mut commands: Commands,
-    q_collidables: Query<(Entity, &Transform, &Collideable), With<Collideable>>,
-    q_colliders: Query<(Entity, &Transform, &Collider), With<Collider>>,
+    mut pool: ResMut<BulletPool>,
+    config: Res<GameConfig>,
+    mut destroyed_events: EventWriter<Destroyed>,
+    mut destroyed: Local<Vec<Entity>>,
+    mut q_collidables: CollidableQuery,
+    mut q_colliders: ColliderQuery,
 ) {
-    for (ent1, collidable_transform, collidable) in q_collidables.iter() {
-        for (ent2, collider_transform, collider) in q_colliders.iter() {
-            if ent1 == ent2 {
+    destroyed.clear();
+    for (ent1, collidable_transform, collidable, mut health, enemy) in q_collidables.iter_mut() {
+        for (
+            ent2,
+            collider_transform,
+            collider,
+            beam,
+            damage,
+            pierce,
+            bullet,
+            player_bullet,
+            enemy_collider,
+            enemy_bullet,
+        ) in q_colliders.iter_mut()
+        {
+            if ent1 == ent2 || pool.is_released(ent2) || destroyed.contains(&ent1) {
                 continue;
             }
-            let objects_distance = collidable_transform
-                .translation
-                .distance(collider_transform.translation);
-            if objects_distance < collidable.radius + collider.radius {
+            // NOTE: nothing hits its own side, and the player is never hit by its bullets
+            let same_side = if enemy.is_some() {
+                enemy_collider.is_some() || enemy_bullet.is_some()
+            } else {
+                player_bullet.is_some()
+            };
+            if same_side || destroyed.contains(&ent2) {
+                continue;
+            }
+            let objects_distance = match beam {
+                Some(beam) => beam.distance(
+                    collider_transform.translation.truncate(),
+                    collidable_transform.translation.truncate(),
+                ),
+                None => collidable_transform
+                    .translation
+                    .distance(collider_transform.translation),
+            };
+            if objects_distance >= collidable.radius + collider.radius {
+                continue;
+            }
+
+            let consumed = match pierce {
+                Some(mut pierce) if pierce.remaining > 0 || pierce.hit.contains(&ent1) => {
+                    if pierce.hit.contains(&ent1) {
+                        continue;
+                    }
+                    pierce.remaining -= 1;
+                    pierce.hit.push(ent1);
+                    false
+                }
+                _ => true,
+            };
+            let dead = match health.as_mut() {
+                Some(health) => {
+                    health.current = health
+                        .current
+                        .saturating_sub(damage.map_or(1, |damage| damage.0));
+                    health.current == 0
+                }
+                None => true,
+            };
+            if dead {
+                destroyed.push(ent1);
+                destroyed_events.send(Destroyed {
+                    entity: ent1,
+                    position: collidable_transform.translation.truncate(),
+                });
                 commands.entity(ent1).despawn_recursive();
-                commands.entity(ent2).despawn_recursive();
             }
+            if consumed {
+                if bullet.is_some() {
+                    pool.release(&mut commands, ent2);
+                } else {
+                    // NOTE: anything else that collides rams into it and is destroyed as well
+                    destroyed.push(ent2);
+                    destroyed_events.send(Destroyed {
+                        entity: ent2,
+                        position: collider_transform.translation.truncate(),
+                    });
+                    commands.entity(ent2).despawn_recursive();
+                }
+            }
+            // NOTE: enemies take every hit, the player gets a moment to get away
+            if !dead && enemy.is_none() {
+                commands
+                    .entity(ent1)
+                    .insert(Invulnerable::new(config.hit_grace_time));
+                break;
+            }
+        }
+    }
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<(Entity, &mut Invulnerable, Option<&IgnoresTimeScale>)>,
+) {
+    for (entity, mut invulnerable, ignores) in query.iter_mut() {
+        if invulnerable
+            .timer
+            .tick(time_scale.delta_for(&time, ignores))
+            .finished()
+        {
+            commands.entity(entity).remove::<Invulnerable>();
         }
     }
 }
 
 fn detect_entity_leaving(
-    windows: Res<Windows>,
+    arena: Res<Arena>,
     query: Query<(Entity, &Transform), With<DetectLeave>>,
     mut event_writer: EventWriter<EntityLeaveWindow>,
 ) {
-    let window = windows.get_primary().unwrap();
     for (entity, transform) in query.iter() {
         let (x, y) = (transform.translation.x, transform.translation.y);
-        let (width, height) = (window.width(), window.height());
+        let (width, height) = (arena.width, arena.height);
         if x > width / 2. {
             event_writer.send(EntityLeaveWindow {
                 entity,
@@ -92,11 +312,20 @@ fn detect_entity_leaving(
 
 fn handle_leave_window_events(
     mut events: EventReader<EntityLeaveWindow>,
-    mut query: Query<(Entity, &mut Transform), With<DetectLeave>>,
+    mut query: Query<(Entity, &mut Transform, Option<&mut Velocity>), With<DetectLeave>>,
 ) {
-    for (entity, mut transform) in query.iter_mut() {
+    for (entity, mut transform, mut velocity) in query.iter_mut() {
         for event in events.iter() {
             if event.entity == entity {
+                // Stop pushing into the edge we just got clamped to
+                if let Some(velocity) = velocity.as_mut() {
+                    if transform.translation.x != event.last_x {
+                        velocity.0.x = 0.;
+                    }
+                    if transform.translation.y != event.last_y {
+                        velocity.0.y = 0.;
+                    }
+                }
                 transform.translation.x = event.last_x;
                 transform.translation.y = event.last_y;
             }
@@ -0,0 +1,426 @@
+use crate::abilities::Cooldown;
+use crate::collide::Health;
+use crate::enemy_data::{check, ChangedFiles, RonFile, RonLoader};
+use crate::game::GameState;
+use crate::game_abilities::{
+    BombAbility, BombStock, BulletTimeAbility, BulletTimeMeter, DashAbility, ShieldAbility,
+};
+use crate::player::Player;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::{Deserialize, Serialize};
+
+/// Keeps the `GameConfig` around, with the `ConfigOverrides` applied, and
+/// applies it to the player whenever it changes.
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameConfig>()
+            .init_resource::<ConfigOverrides>()
+            .add_startup_system(apply_config_overrides)
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(retune_player));
+    }
+}
+
+/// Loads the `GameConfig` from `assets/data/game.config.ron`, and again every
+/// time the file changes, see `ChangedFiles`.
+pub struct ConfigFilePlugin;
+
+impl Plugin for ConfigFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ConfigFile>()
+            .init_asset_loader::<RonLoader<ConfigFile>>()
+            .add_system(reload_config);
+    }
+}
+
+/// The numbers behind the player and its bullets. Enemies are tuned in their
+/// archetype file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub player_speed: f32,
+    /// NOTE: the player keeps its size for the whole run, a change waits for the next one
+    pub player_radius: f32,
+    /// Hits the player takes before the run is over
+    pub player_health: u32,
+    /// Invulnerability after a hit that was not the last one
+    pub hit_grace_time: f32,
+    pub shoot_cooldown: f32,
+    /// Speed of the player's shots and of the enemies' aimed ones
+    pub bullet_speed: f32,
+    pub bullet_radius: f32,
+    /// How far in front of the shooter bullets appear
+    pub muzzle_offset: f32,
+    pub dash_distance: f32,
+    pub dash_duration: f32,
+    pub dash_cooldown: f32,
+    /// Invulnerability left after a dash ends
+    pub dash_grace_time: f32,
+    pub shield_cooldown: f32,
+    /// Time at the start of a block that reflects bullets instead
+    pub parry_window: f32,
+    pub block_duration: f32,
+    /// Reach of a bomb, bigger than the arena to clear the whole screen
+    pub bomb_radius: f32,
+    pub bomb_cooldown: f32,
+    /// Invulnerability after a bomb
+    pub bomb_grace_time: f32,
+    /// Bombs the player starts a run with, and the most it can hold
+    pub bomb_stock: u32,
+    /// Kills that give back a used bomb
+    pub kills_per_bomb: u32,
+    /// Health a bomb takes off every enemy in reach
+    pub bomb_damage: u32,
+    /// How fast the world runs during bullet time, 1 being full speed
+    pub bullet_time_scale: f32,
+    /// Seconds of bullet time a full meter holds
+    pub bullet_time_seconds: f32,
+    /// Seconds the meter gains back per second
+    pub bullet_time_refill: f32,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            player_speed: 80.,
+            player_radius: 20.,
+            player_health: 3,
+            hit_grace_time: 1.,
+            shoot_cooldown: 0.3,
+            bullet_speed: 250.,
+            bullet_radius: 8.,
+            muzzle_offset: 30.,
+            dash_distance: 150.,
+            dash_duration: 0.15,
+            dash_cooldown: 10.,
+            dash_grace_time: 0.1,
+            shield_cooldown: 5.,
+            parry_window: 0.2,
+            block_duration: 1.,
+            bomb_radius: 250.,
+            bomb_cooldown: 1.,
+            bomb_grace_time: 1.5,
+            bomb_stock: 3,
+            kills_per_bomb: 20,
+            bomb_damage: 5,
+            bullet_time_scale: 0.35,
+            bullet_time_seconds: 3.,
+            bullet_time_refill: 0.2,
+        }
+    }
+}
+
+impl GameConfig {
+    fn values_mut(&mut self) -> [(&'static str, &mut f32); 20] {
+        [
+            ("player_speed", &mut self.player_speed),
+            ("player_radius", &mut self.player_radius),
+            ("hit_grace_time", &mut self.hit_grace_time),
+            ("shoot_cooldown", &mut self.shoot_cooldown),
+            ("bullet_speed", &mut self.bullet_speed),
+            ("bullet_radius", &mut self.bullet_radius),
+            ("muzzle_offset", &mut self.muzzle_offset),
+            ("dash_distance", &mut self.dash_distance),
+            ("dash_duration", &mut self.dash_duration),
+            ("dash_cooldown", &mut self.dash_cooldown),
+            ("dash_grace_time", &mut self.dash_grace_time),
+            ("shield_cooldown", &mut self.shield_cooldown),
+            ("parry_window", &mut self.parry_window),
+            ("block_duration", &mut self.block_duration),
+            ("bomb_radius", &mut self.bomb_radius),
+            ("bomb_cooldown", &mut self.bomb_cooldown),
+            ("bomb_grace_time", &mut self.bomb_grace_time),
+            ("bullet_time_scale", &mut self.bullet_time_scale),
+            ("bullet_time_seconds", &mut self.bullet_time_seconds),
+            ("bullet_time_refill", &mut self.bullet_time_refill),
+        ]
+    }
+
+    fn counts_mut(&mut self) -> [(&'static str, &mut u32); 4] {
+        [
+            ("player_health", &mut self.player_health),
+            ("bomb_stock", &mut self.bomb_stock),
+            ("kills_per_bomb", &mut self.kills_per_bomb),
+            ("bomb_damage", &mut self.bomb_damage),
+        ]
+    }
+
+    /// Sets a value by its name in the config file, unless that makes the
+    /// config invalid.
+    pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+        let mut config = self.clone();
+        let count = config
+            .counts_mut()
+            .into_iter()
+            .find(|(field, _)| *field == name);
+        if let Some((_, count)) = count {
+            check(value.fract() == 0. && value >= 0., || {
+                format!("{} has to be a whole number, not {}", name, value)
+            })?;
+            *count = value as u32;
+        } else {
+            let (_, field) = config
+                .values_mut()
+                .into_iter()
+                .find(|(field, _)| *field == name)
+                .ok_or_else(|| format!("there is no {} to set", name))?;
+            *field = value;
+        }
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (name, count) in self.clone().counts_mut() {
+            check(*count > 0, || format!("{} has to be positive, not 0", name))?;
+        }
+        for (name, value) in self.clone().values_mut() {
+            // NOTE: the rest either divides by it or makes no sense at zero
+            let may_be_zero = matches!(
+                name,
+                "hit_grace_time"
+                    | "shoot_cooldown"
+                    | "muzzle_offset"
+                    | "dash_grace_time"
+                    | "parry_window"
+                    | "bomb_grace_time"
+            );
+            if may_be_zero {
+                check(*value >= 0., || {
+                    format!("{} can not be negative, got {}", name, value)
+                })?;
+            } else {
+                check(*value > 0., || {
+                    format!("{} has to be positive, not {}", name, value)
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `GameConfig` values set on the command line, they win over the config file.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigOverrides(Vec<(String, f32)>);
+
+impl ConfigOverrides {
+    /// `--set <name>=<value>`, as often as needed.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut overrides = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg != "--set" {
+                continue;
+            }
+            let setting = args.next().ok_or("--set needs a <name>=<value>")?;
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("{} is not a <name>=<value>", setting))?;
+            let value: f32 = value
+                .parse()
+                .map_err(|_| format!("{} is not a number", value))?;
+            // NOTE: catches unknown names and bad values right away
+            GameConfig::default().set(name, value)?;
+            overrides.push((name.to_string(), value));
+        }
+        Ok(Self(overrides))
+    }
+
+    fn apply(&self, config: &mut GameConfig) {
+        for (name, value) in &self.0 {
+            if let Err(err) = config.set(name, *value) {
+                eprintln!("Ignoring --set {}={}: {}", name, value, err);
+            }
+        }
+    }
+}
+
+#[derive(TypeUuid)]
+#[uuid = "3a9e4f61-0c2b-4d8e-b7f5-81d6c2a4e953"]
+pub struct ConfigFile {
+    path: String,
+    data: Result<GameConfig, String>,
+}
+
+impl RonFile for ConfigFile {
+    type Data = GameConfig;
+    const EXTENSIONS: &'static [&'static str] = &["config.ron"];
+
+    fn new(path: String, data: Result<Self::Data, String>) -> Self {
+        Self { path, data }
+    }
+}
+
+fn apply_config_overrides(overrides: Res<ConfigOverrides>, mut config: ResMut<GameConfig>) {
+    overrides.apply(&mut config);
+}
+
+fn reload_config(
+    mut files: ChangedFiles<ConfigFile>,
+    overrides: Res<ConfigOverrides>,
+    mut config: ResMut<GameConfig>,
+) {
+    for file in files.read() {
+        let data = file
+            .data
+            .clone()
+            .and_then(|data| data.validate().map(|()| data));
+        match data {
+            Ok(data) => {
+                *config = data;
+                overrides.apply(&mut config);
+            }
+            Err(err) => eprintln!("{}: {}, keeping the current config", file.path, err),
+        }
+    }
+}
+
+type RetunedAbilitiesQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Cooldown,
+        Option<&'static mut DashAbility>,
+        Option<&'static mut ShieldAbility>,
+        Option<&'static mut BombAbility>,
+        Option<&'static mut BombStock>,
+    ),
+>;
+
+/// NOTE: new runs pick up the `GameConfig` on their own, this updates the current one.
+/// Speed and shoot cooldown depend on the player's buffs, see `tick_buffs`, and
+/// the radius is left for the next run.
+fn retune_player(
+    config: Res<GameConfig>,
+    mut q_player: Query<(&mut Health, &Children), With<Player>>,
+    mut q_abilities: RetunedAbilitiesQuery,
+    mut q_bullet_time: Query<(&mut BulletTimeAbility, &mut BulletTimeMeter)>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for (mut health, children) in q_player.iter_mut() {
+        // NOTE: keeps the hits taken so far
+        let taken = health.max - health.current;
+        *health = Health::new(config.player_health);
+        health.current = config.player_health.saturating_sub(taken).max(1);
+        for &child in children.iter() {
+            if let Ok((mut bullet_time, mut meter)) = q_bullet_time.get_mut(child) {
+                bullet_time.time_scale = config.bullet_time_scale;
+                bullet_time.refill_rate = config.bullet_time_refill;
+                meter.max = config.bullet_time_seconds;
+                meter.seconds = meter.seconds.min(meter.max);
+                continue;
+            }
+            let (mut cooldown, dash, shield, bomb, stock) = match q_abilities.get_mut(child) {
+                Ok(ability) => ability,
+                Err(_) => continue,
+            };
+            if let Some(mut dash) = dash {
+                dash.distance = config.dash_distance;
+                dash.duration = config.dash_duration;
+                cooldown.set_duration(config.dash_cooldown);
+            }
+            if let Some(mut shield) = shield {
+                shield.parry_window = config.parry_window;
+                shield.block_duration = config.block_duration;
+                cooldown.set_duration(config.shield_cooldown);
+            }
+            if let Some(mut bomb) = bomb {
+                bomb.radius = config.bomb_radius;
+                bomb.grace_time = config.bomb_grace_time;
+                bomb.damage = config.bomb_damage;
+                cooldown.set_duration(config.bomb_cooldown);
+            }
+            if let Some(mut stock) = stock {
+                stock.max = config.bomb_stock;
+                stock.bombs = stock.bombs.min(stock.max);
+                stock.kills_per_bomb = config.kills_per_bomb;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(args: &[&str]) -> Result<ConfigOverrides, String> {
+        ConfigOverrides::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn the_default_config_is_valid() {
+        assert_eq!(GameConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn set_changes_a_value_by_name() {
+        let mut config = GameConfig::default();
+        config.set("dash_distance", 200.).unwrap();
+        config.set("player_health", 5.).unwrap();
+        config.set("bomb_damage", 2.).unwrap();
+        assert_eq!(config.dash_distance, 200.);
+        assert_eq!(config.player_health, 5);
+        assert_eq!(config.bomb_damage, 2);
+    }
+
+    #[test]
+    fn set_refuses_unknown_names() {
+        let mut config = GameConfig::default();
+        let err = config.set("player_sped", 100.).unwrap_err();
+        assert!(err.contains("player_sped"), "{}", err);
+        assert_eq!(config, GameConfig::default());
+    }
+
+    #[test]
+    fn set_refuses_values_out_of_range() {
+        let mut config = GameConfig::default();
+        assert!(config.set("player_speed", 0.).is_err());
+        assert!(config.set("dash_cooldown", -1.).is_err());
+        assert!(config.set("player_health", 0.).is_err());
+        assert!(config.set("player_health", 2.5).is_err());
+        assert!(config.set("kills_per_bomb", -20.).is_err());
+        assert_eq!(config, GameConfig::default());
+
+        // NOTE: a few values may be zero, but never negative
+        config.set("hit_grace_time", 0.).unwrap();
+        assert!(config.set("hit_grace_time", -0.5).is_err());
+        assert_eq!(config.hit_grace_time, 0.);
+    }
+
+    #[test]
+    fn overrides_apply_every_set_argument() {
+        let overrides = overrides(&[
+            "--seed",
+            "4",
+            "--set",
+            "player_speed=120",
+            "--set",
+            "bomb_radius=300.5",
+        ])
+        .unwrap();
+        let mut config = GameConfig::default();
+        overrides.apply(&mut config);
+        assert_eq!(config.player_speed, 120.);
+        assert_eq!(config.bomb_radius, 300.5);
+    }
+
+    #[test]
+    fn overrides_refuse_malformed_arguments() {
+        assert!(overrides(&["--set"]).is_err());
+        assert!(overrides(&["--set", "player_speed"]).is_err());
+        assert!(overrides(&["--set", "player_speed=fast"]).is_err());
+    }
+
+    #[test]
+    fn overrides_refuse_unknown_names_and_bad_values() {
+        let err = overrides(&["--set", "bullet_sped=10"]).unwrap_err();
+        assert!(err.contains("bullet_sped"), "{}", err);
+        let err = overrides(&["--set", "bullet_speed=-10"]).unwrap_err();
+        assert!(err.contains("bullet_speed"), "{}", err);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::collide::Health;
+use crate::game::GameState;
+use crate::player::Player;
+use crate::timestep::{FixedTime, FixedUpdateStage};
+use crate::waves::WaveProgress;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Most the dynamic difficulty moves the preset's scaling, either way.
+const MAX_ADJUSTMENT: f32 = 0.25;
+/// Adjustment for every hit the player takes
+const HIT_ADJUSTMENT: f32 = -0.1;
+/// Waves cleared faster than this make the game harder, slower ones easier.
+const FAST_WAVE_SECONDS: f32 = 15.;
+const SLOW_WAVE_SECONDS: f32 = 40.;
+const WAVE_ADJUSTMENT: f32 = 0.05;
+
+/// Scales the enemies by the `DifficultySettings` picked in the menu, and
+/// nudges them while playing if the dynamic difficulty is on.
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DifficultySettings>()
+            .init_resource::<DynamicDifficulty>()
+            .init_resource::<DifficultyScaling>()
+            .add_system_set(
+                // NOTE: a replay brings its own settings
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(reset_difficulty.after("replay")),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new().with_system(adjust_difficulty.after("collision").after("waves")),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Nightmare,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Nightmare,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Nightmare => "Nightmare",
+        }
+    }
+
+    /// The next harder preset, back to the easiest after the hardest one.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&preset| preset == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn scaling(self) -> DifficultyScaling {
+        match self {
+            Difficulty::Easy => DifficultyScaling {
+                enemy_speed: 0.8,
+                fire_rate: 0.7,
+                bullet_speed: 0.8,
+                enemy_health: 0.75,
+                wave_size: 0.75,
+            },
+            Difficulty::Normal => DifficultyScaling::default(),
+            Difficulty::Hard => DifficultyScaling {
+                enemy_speed: 1.2,
+                fire_rate: 1.3,
+                bullet_speed: 1.15,
+                enemy_health: 1.5,
+                wave_size: 1.25,
+            },
+            Difficulty::Nightmare => DifficultyScaling {
+                enemy_speed: 1.4,
+                fire_rate: 1.7,
+                bullet_speed: 1.3,
+                enemy_health: 2.,
+                wave_size: 1.5,
+            },
+        }
+    }
+}
+
+/// Picked in the menu before a run, and recorded with its replay.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DifficultySettings {
+    pub preset: Difficulty,
+    /// Whether `DynamicDifficulty` adjusts the preset during the run
+    pub dynamic: bool,
+}
+
+/// Factors the enemies of the current run are scaled by, 1 on `Normal`.
+///
+/// NOTE: enemies are scaled once when spawned, later changes only apply to
+/// the next ones, and to every aimed shot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DifficultyScaling {
+    pub enemy_speed: f32,
+    pub fire_rate: f32,
+    pub bullet_speed: f32,
+    pub enemy_health: f32,
+    /// Enemies per wave compared to the wave script
+    pub wave_size: f32,
+}
+
+impl Default for DifficultyScaling {
+    fn default() -> Self {
+        Self {
+            enemy_speed: 1.,
+            fire_rate: 1.,
+            bullet_speed: 1.,
+            enemy_health: 1.,
+            wave_size: 1.,
+        }
+    }
+}
+
+impl DifficultyScaling {
+    /// Every factor raised (or lowered) by the same fraction.
+    pub fn adjusted(self, adjustment: f32) -> Self {
+        let factor = 1. + adjustment;
+        Self {
+            enemy_speed: self.enemy_speed * factor,
+            fire_rate: self.fire_rate * factor,
+            bullet_speed: self.bullet_speed * factor,
+            enemy_health: self.enemy_health * factor,
+            wave_size: self.wave_size * factor,
+        }
+    }
+
+    pub fn cooldown(&self, seconds: f32) -> f32 {
+        seconds / self.fire_rate
+    }
+
+    pub fn health(&self, health: u32) -> u32 {
+        ((health as f32 * self.enemy_health).round() as u32).max(1)
+    }
+
+    pub fn wave_size(&self, enemies: usize) -> usize {
+        ((enemies as f32 * self.wave_size).round() as usize).max(1)
+    }
+}
+
+/// How the player is doing, and what the difficulty made of it so far.
+#[derive(Default)]
+pub struct DynamicDifficulty {
+    /// Fraction the preset's scaling is raised by, lowered when negative
+    pub adjustment: f32,
+    player_health: Option<u32>,
+    waves_spawned: usize,
+    wave_time: f32,
+}
+
+impl DynamicDifficulty {
+    fn adjust(&mut self, by: f32) {
+        self.adjustment = (self.adjustment + by).clamp(-MAX_ADJUSTMENT, MAX_ADJUSTMENT);
+    }
+}
+
+fn reset_difficulty(
+    settings: Res<DifficultySettings>,
+    mut dynamic: ResMut<DynamicDifficulty>,
+    mut scaling: ResMut<DifficultyScaling>,
+) {
+    *dynamic = DynamicDifficulty::default();
+    *scaling = settings.preset.scaling();
+}
+
+/// Eases off when the player gets hit or takes long to clear a wave, and
+/// pushes harder when waves go down quickly.
+fn adjust_difficulty(
+    time: Res<FixedTime>,
+    settings: Res<DifficultySettings>,
+    progress: Res<WaveProgress>,
+    mut dynamic: ResMut<DynamicDifficulty>,
+    mut scaling: ResMut<DifficultyScaling>,
+    q_player: Query<&Health, With<Player>>,
+) {
+    if !settings.dynamic {
+        return;
+    }
+    let before = dynamic.adjustment;
+
+    if let Ok(health) = q_player.get_single() {
+        let lost = dynamic
+            .player_health
+            .map_or(0, |last| last.saturating_sub(health.current));
+        dynamic.adjust(lost as f32 * HIT_ADJUSTMENT);
+        dynamic.player_health = Some(health.current);
+    }
+
+    dynamic.wave_time += time.delta_seconds();
+    if progress.waves_spawned() > dynamic.waves_spawned {
+        // NOTE: the first wave shows up right away, there is nothing to judge yet
+        if dynamic.waves_spawned > 0 {
+            if dynamic.wave_time < FAST_WAVE_SECONDS {
+                dynamic.adjust(WAVE_ADJUSTMENT);
+            } else if dynamic.wave_time > SLOW_WAVE_SECONDS {
+                dynamic.adjust(-WAVE_ADJUSTMENT);
+            }
+        }
+        dynamic.waves_spawned = progress.waves_spawned();
+        dynamic.wave_time = 0.;
+    }
+
+    if dynamic.adjustment != before {
+        *scaling = settings.preset.scaling().adjusted(dynamic.adjustment);
+    }
+}
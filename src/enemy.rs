@@ -1,70 +1,187 @@
+use bevy::ecs::system::{EntityCommands, SystemParam};
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
+use rand::Rng;
+use std::f32::consts::PI;
 
 use crate::{
     abilities::*,
-    bullet::{create_bullet_bundle, BulletAttributes},
-    collide::{Collideable, Collider, DetectLeave},
+    bullet::{muzzle_transform, BulletAttributes},
+    bullet_pool::{BulletPool, BulletStyle},
+    collide::{Collideable, Collider, Destroyed, DetectLeave, Health},
+    config::GameConfig,
+    difficulty::DifficultyScaling,
+    enemy_ai::*,
+    enemy_archetype::{EnemyArchetypes, EnemyKind},
     game::*,
     game_abilities::*,
+    movement::*,
     player::{Player, PlayerBullet},
+    rng::{GameRng, RngStream},
+    timestep::{FixedTime, FixedUpdateStage, TimeScale},
+    utils::intercept_angle,
 };
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_enemy))
-            .add_system_set(
-                SystemSet::on_update(GameState::Playing)
+        app.init_resource::<EnemyArchetypes>()
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new()
                     .with_system(move_enemy.label("movement"))
-                    .with_system(shoot_action.after("movement")),
+                    .with_system(
+                        shoot_action
+                            .label("aiming")
+                            .label("enemy_shooting")
+                            .after("action"),
+                    )
+                    .with_system(fire_emitters.label("aiming").after("enemy_shooting"))
+                    // NOTE: the destroyed enemy is only despawned at the end of the step
+                    .with_system(split_destroyed_enemies.after("collision").after("bomb")),
             );
     }
 }
 
 #[derive(Component)]
-struct Enemy;
+pub struct Enemy;
 
 #[derive(Component)]
-struct EnemyBullet;
+pub struct EnemyBullet;
 
-fn spawn_enemy(mut commands: Commands) {
+/// Breaks into `count` enemies of `kind` when destroyed.
+#[derive(Component, Clone, Copy)]
+pub struct SplitsOnDeath {
+    pub kind: EnemyKind,
+    pub count: u32,
+}
+
+/// Spawns enemies the way the `EnemyArchetypes` and the difficulty say.
+#[derive(SystemParam)]
+pub struct EnemySpawner<'w, 's> {
+    pub commands: Commands<'w, 's>,
+    pub archetypes: Res<'w, EnemyArchetypes>,
+    pub difficulty: Res<'w, DifficultyScaling>,
+}
+
+impl<'w, 's> EnemySpawner<'w, 's> {
+    pub fn spawn(&mut self, kind: EnemyKind, position: Vec2) -> Entity {
+        spawn_enemy(
+            &mut self.commands,
+            &self.archetypes,
+            &self.difficulty,
+            kind,
+            position,
+        )
+    }
+}
+
+/// Builds an enemy of the given kind, abilities included.
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    archetypes: &EnemyArchetypes,
+    difficulty: &DifficultyScaling,
+    kind: EnemyKind,
+    position: Vec2,
+) -> Entity {
+    let archetype = archetypes.get(kind).scaled(difficulty);
     let shape = shapes::RegularPolygon {
-        sides: 5,
-        feature: shapes::RegularPolygonFeature::Radius(BASE_RADIUS),
+        sides: archetype.sides,
+        feature: shapes::RegularPolygonFeature::Radius(archetype.radius),
         ..shapes::RegularPolygon::default()
     };
 
-    let enemy = commands
-        .spawn_bundle(GeometryBuilder::build_as(
-            &shape,
-            DrawMode::Outlined {
-                fill_mode: FillMode::color(Color::RED),
-                outline_mode: StrokeMode::new(Color::BLACK, 0.0),
-            },
-            Transform {
-                translation: Vec3::new(350.0, 0.0, 10.0),
-                ..Default::default()
-            },
-        ))
+    let mut enemy = commands.spawn_bundle(GeometryBuilder::build_as(
+        &shape,
+        DrawMode::Outlined {
+            fill_mode: FillMode::color(archetype.color),
+            outline_mode: StrokeMode::new(Color::BLACK, 0.0),
+        },
+        Transform {
+            translation: position.extend(10.0),
+            ..Default::default()
+        },
+    ));
+    enemy
         .insert(Enemy)
-        .insert(Speed(BASE_SPEED))
+        .insert(kind)
+        .insert(EnemyAi::new(archetype.ai))
+        .insert(Speed(archetype.speed))
+        .insert_bundle(MovementBundle::default())
+        .insert(archetype.handling)
+        .insert(Health::new(archetype.health))
         .insert(Collideable {
-            radius: BASE_RADIUS,
+            radius: archetype.radius,
         })
-        .insert(DetectLeave)
-        .id();
+        .insert(DetectLeave);
+    if let Some(aim) = archetype.aim {
+        enemy.insert(aim);
+    }
+    if archetype.rams {
+        enemy.insert(Collider {
+            radius: archetype.radius,
+        });
+    }
+    if let Some((kind, count)) = archetype.splits_into {
+        enemy.insert(SplitsOnDeath { kind, count });
+    }
+    let enemy = enemy.id();
 
-    let shoot_ability = commands
-        .spawn_bundle(ShootAbilityBundle {
-            marker: ShootAbility,
-            cooldown: Cooldown::new(0.3),
-        })
-        .insert(Ability)
-        .id();
+    let abilities = spawn_abilities(commands, archetype.shoot_cooldown, archetype.emitter);
+    commands.entity(enemy).push_children(&abilities);
+    enemy
+}
 
-    commands.entity(enemy).push_children(&[shoot_ability]);
+/// Spawns an enemy's aimed shot and bullet pattern, to be added as its children.
+pub fn spawn_abilities(
+    commands: &mut Commands,
+    shoot_cooldown: Option<f32>,
+    emitter: Option<(BulletEmitter, f32)>,
+) -> Vec<Entity> {
+    let mut abilities = Vec::new();
+    if let Some(cooldown) = shoot_cooldown {
+        abilities.push(
+            commands
+                .spawn_bundle(ShootAbilityBundle {
+                    marker: ShootAbility,
+                    cooldown: Cooldown::new(cooldown),
+                })
+                .insert(Ability)
+                .id(),
+        );
+    }
+    if let Some((emitter, cooldown)) = emitter {
+        abilities.push(
+            commands
+                .spawn_bundle(BulletEmitterBundle {
+                    emitter,
+                    cooldown: Cooldown::new(cooldown),
+                })
+                .insert(Ability)
+                .id(),
+        );
+    }
+    abilities
+}
+
+fn split_destroyed_enemies(
+    mut spawner: EnemySpawner,
+    mut events: EventReader<Destroyed>,
+    q_splits: Query<&SplitsOnDeath>,
+) {
+    for event in events.iter() {
+        let splits = match q_splits.get(event.entity) {
+            Ok(splits) => splits,
+            Err(_) => continue,
+        };
+        let radius = spawner.archetypes.get(splits.kind).radius;
+        for i in 0..splits.count {
+            let angle = i as f32 / splits.count as f32 * 2. * PI;
+            let offset = radius * Vec2::new(angle.cos(), angle.sin());
+            spawner.spawn(splits.kind, event.position + offset);
+        }
+    }
 }
 
 struct ClosestBullet {
@@ -72,90 +189,257 @@ struct ClosestBullet {
     good_question: Vec2,
 }
 
-fn move_enemy(
-    time: Res<Time>,
-    mut enemy_query: Query<(&mut Transform, &Speed), With<Enemy>>,
-    bullets_query: Query<(&Transform, &BulletAttributes), (With<PlayerBullet>, Without<Enemy>)>,
-) {
-    if let Ok((mut transform, speed)) = enemy_query.get_single_mut() {
-        let mut dangerous_bullets: Vec<ClosestBullet> = Vec::new();
-        let mut closest_distance = std::f32::MAX;
-        for (bullet_transform, attributes) in bullets_query.iter() {
-            let distance = bullet_transform.translation.distance(transform.translation);
-
-            let relative_position =
-                (transform.translation - bullet_transform.translation).truncate();
-            let relative_angle = Vec2::X.angle_between(relative_position);
-            let radius = BASE_RADIUS + 12.;
-            let transformed_angle = attributes.angle - relative_angle;
-            if transformed_angle.abs() < (radius / (distance.powi(2) + radius.powi(2)).sqrt())
-                || distance < BASE_RADIUS + 15.
-            {
-                let x_sign: f32 = if transformed_angle.is_sign_positive() {
-                    -1.
-                } else {
-                    1.
-                };
-                dangerous_bullets.push(ClosestBullet {
-                    distance,
-                    good_question: Vec2::new(
-                        attributes.angle.sin(),
-                        x_sign * attributes.angle.cos(),
-                    ),
-                });
-                if distance < closest_distance {
-                    closest_distance = distance;
-                }
+fn assess_threat<'a>(
+    transform: &Transform,
+    collideable: &Collideable,
+    bullets: impl Iterator<Item = (&'a Transform, &'a BulletAttributes)>,
+) -> Threat {
+    let mut dangerous_bullets: Vec<ClosestBullet> = Vec::new();
+    let mut closest_distance = f32::MAX;
+    for (bullet_transform, attributes) in bullets {
+        let distance = bullet_transform.translation.distance(transform.translation);
+
+        let relative_position = (transform.translation - bullet_transform.translation).truncate();
+        let relative_angle = Vec2::X.angle_between(relative_position);
+        let radius = collideable.radius + 12.;
+        let transformed_angle = attributes.angle - relative_angle;
+        if transformed_angle.abs() < (radius / (distance.powi(2) + radius.powi(2)).sqrt())
+            || distance < collideable.radius + 15.
+        {
+            let x_sign: f32 = if transformed_angle.is_sign_positive() {
+                -1.
+            } else {
+                1.
+            };
+            dangerous_bullets.push(ClosestBullet {
+                distance,
+                good_question: Vec2::new(attributes.angle.sin(), x_sign * attributes.angle.cos()),
+            });
+            if distance < closest_distance {
+                closest_distance = distance;
             }
         }
-        dangerous_bullets.retain(|bullet| bullet.distance < closest_distance * 2.);
-
-        let mut inverted_distances = dangerous_bullets
-            .iter()
-            .map(|bullet| 1. / bullet.distance)
-            .collect::<Vec<_>>();
-        let sum_inv_distances = inverted_distances.iter().sum::<f32>();
-        inverted_distances.iter_mut().for_each(|inv_distance| {
-            *inv_distance /= sum_inv_distances;
+    }
+    dangerous_bullets.retain(|bullet| bullet.distance < closest_distance * 2.);
+
+    let mut inverted_distances = dangerous_bullets
+        .iter()
+        .map(|bullet| 1. / bullet.distance)
+        .collect::<Vec<_>>();
+    let sum_inv_distances = inverted_distances.iter().sum::<f32>();
+    inverted_distances.iter_mut().for_each(|inv_distance| {
+        *inv_distance /= sum_inv_distances;
+    });
+    let mut direction = Vec2::ZERO;
+    dangerous_bullets
+        .iter()
+        .zip(inverted_distances.iter())
+        .for_each(|(bullet, inv_distance)| {
+            direction += bullet.good_question * *inv_distance;
         });
-        let mut direction = Vec2::ZERO;
-        dangerous_bullets
-            .iter()
-            .zip(inverted_distances.iter())
-            .for_each(|(bullet, inv_distance)| {
-                direction += bullet.good_question * *inv_distance;
-            });
-        transform.translation += direction.extend(0.) * speed.0 * time.delta_seconds();
+
+    Threat {
+        dangerous_bullets: dangerous_bullets.len(),
+        dodge: direction,
+    }
+}
+
+type PlayerBulletQuery<'w, 's> = Query<
+    'w,
+    's,
+    (&'static Transform, &'static BulletAttributes),
+    (With<PlayerBullet>, Without<Enemy>),
+>;
+
+fn move_enemy(
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut enemy_query: Query<(&Transform, &Collideable, &mut Thrust, &mut EnemyAi), With<Enemy>>,
+    q_player: Query<&Transform, (With<Player>, Without<Enemy>)>,
+    bullets_query: PlayerBulletQuery,
+) {
+    let player_position = q_player
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.truncate());
+
+    for (transform, collideable, mut thrust, mut ai) in enemy_query.iter_mut() {
+        let threat = assess_threat(transform, collideable, bullets_query.iter());
+        let to_player = player_position
+            .map(|player_position| player_position - transform.translation.truncate());
+
+        ai.update(
+            time_scale.delta(&time),
+            to_player.map(Vec2::length),
+            &threat,
+        );
+        thrust.0 = ai.steering(to_player.unwrap_or(Vec2::ZERO), &threat);
+    }
+}
+
+/// How well an enemy type aims at the player.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct AimSkill {
+    /// Shoot where the player is going to be instead of where it is
+    pub leads_target: bool,
+    /// Maximum angle (radians) a shot can be off by
+    pub max_error: f32,
+}
+
+impl AimSkill {
+    pub const SHARPSHOOTER: AimSkill = AimSkill {
+        leads_target: true,
+        max_error: 0.1,
+    };
+}
+
+fn aim_at_player(
+    enemy: &Transform,
+    player: &Transform,
+    player_velocity: &Velocity,
+    bullet_speed: f32,
+    skill: Option<&AimSkill>,
+    rng: &mut GameRng,
+) -> f32 {
+    let (from, to) = (enemy.translation.truncate(), player.translation.truncate());
+    let direct = Vec2::X.angle_between(to - from);
+    let skill = match skill {
+        Some(skill) => skill,
+        None => return direct,
+    };
+
+    let angle = if skill.leads_target {
+        intercept_angle(from, to, player_velocity.0, bullet_speed).unwrap_or(direct)
+    } else {
+        direct
+    };
+    if skill.max_error <= 0. {
+        return angle;
+    }
+    angle
+        + rng
+            .stream(RngStream::EnemyAim)
+            .gen_range(-skill.max_error..=skill.max_error)
+}
+
+/// What enemies fire their bullets with.
+#[derive(SystemParam)]
+struct EnemyGuns<'w, 's> {
+    commands: Commands<'w, 's>,
+    pool: ResMut<'w, BulletPool>,
+    config: Res<'w, GameConfig>,
+}
+
+impl<'w, 's> EnemyGuns<'w, 's> {
+    /// Fires a bullet towards `angle` from in front of the `shooter`.
+    fn fire(
+        &mut self,
+        shooter: &Transform,
+        angle: f32,
+        speed: f32,
+        color: Color,
+    ) -> EntityCommands<'w, 's, '_> {
+        let mut bullet_transform = *shooter;
+        bullet_transform.translation.z -= 1.;
+        let style = BulletStyle {
+            radius: self.config.bullet_radius,
+            color,
+        };
+        let mut bullet = self.pool.spawn(
+            &mut self.commands,
+            muzzle_transform(bullet_transform, angle, self.config.muzzle_offset),
+            angle,
+            speed,
+            style,
+        );
+        bullet
+            .insert(Collider {
+                radius: self.config.bullet_radius,
+            })
+            .insert(EnemyBullet);
+        bullet
     }
 }
 
 fn shoot_action(
-    mut commands: Commands,
-    q_enemy: Query<(&Transform, &Children), With<Enemy>>,
-    q_player: Query<&Transform, With<Player>>,
-    mut q_child: Query<&mut Cooldown>,
+    mut guns: EnemyGuns,
+    mut rng: ResMut<GameRng>,
+    difficulty: Res<DifficultyScaling>,
+    mut q_ability: Query<(&Parent, &mut Cooldown), With<ShootAbility>>,
+    q_enemy: Query<(&Transform, Option<&AimSkill>), With<Enemy>>,
+    q_player: Query<(&Transform, &Velocity), With<Player>>,
 ) {
-    if let Ok((enemy_transform, children)) = q_enemy.get_single() {
-        if let Ok(player_transform) = q_player.get_single() {
-            let mut bullet_transform = enemy_transform.clone();
-            bullet_transform.translation.z -= 1.;
-            let direction =
-                player_transform.translation.truncate() - enemy_transform.translation.truncate();
-            let angle = Vec2::X.angle_between(direction);
-            let mut cd = q_child.get_mut(children[0]).unwrap();
-            if !cd.finished() {
-                return;
-            }
-            commands
-                .spawn_bundle(create_bullet_bundle(
-                    bullet_transform,
+    let (player_transform, player_velocity) = match q_player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    for (parent, mut cd) in q_ability.iter_mut() {
+        // NOTE: the player's shoot ability has no `Enemy` parent
+        let (enemy_transform, skill) = match q_enemy.get(parent.0) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
+        if !cd.finished() {
+            continue;
+        }
+        let bullet_speed = guns.config.bullet_speed * difficulty.bullet_speed;
+        let angle = aim_at_player(
+            enemy_transform,
+            player_transform,
+            player_velocity,
+            bullet_speed,
+            skill,
+            &mut rng,
+        );
+        guns.fire(enemy_transform, angle, bullet_speed, Color::ORANGE_RED);
+        cd.start();
+    }
+}
+
+fn fire_emitters(
+    mut guns: EnemyGuns,
+    mut rng: ResMut<GameRng>,
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut q_emitter: Query<(&Parent, &mut BulletEmitter, &mut Cooldown)>,
+    q_enemy: Query<(&Transform, Option<&AimSkill>), With<Enemy>>,
+    q_player: Query<(&Transform, &Velocity), With<Player>>,
+) {
+    for (parent, mut emitter, mut cd) in q_emitter.iter_mut() {
+        let (enemy_transform, skill) = match q_enemy.get(parent.0) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
+        let volleys = emitter.tick(time_scale.delta_seconds(&time), &mut cd);
+        if volleys.is_empty() {
+            continue;
+        }
+        // NOTE: aimed only when firing, every aim rolls the RNG
+        let aim = q_player
+            .get_single()
+            .map(|(player_transform, player_velocity)| {
+                aim_at_player(
+                    enemy_transform,
+                    player_transform,
+                    player_velocity,
+                    emitter.bullet_speed,
+                    skill,
+                    &mut rng,
+                )
+            })
+            .unwrap_or(0.);
+
+        for volley in volleys {
+            for angle in emitter.volley_angles(volley, aim) {
+                guns.fire(
+                    enemy_transform,
                     angle,
-                    BULLET_SPEED,
-                    Color::ORANGE_RED,
-                ))
-                .insert(Collider { radius: 8.0 })
-                .insert(EnemyBullet);
-            cd.start();
+                    emitter.bullet_speed,
+                    emitter.bullet_color,
+                )
+                .insert(emitter.bullet_behaviour.bind(parent.0))
+                .insert(emitter.bullet_limits);
+            }
         }
     }
 }
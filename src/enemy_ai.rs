@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AiState {
+    /// Player out of sight, stand still
+    Idle,
+    /// Close the distance to the player
+    Approach,
+    /// Circle around the player at the preferred distance
+    Strafe,
+    /// Back off to the preferred distance
+    Retreat,
+    /// Sidestep the incoming player bullets
+    Dodge,
+    /// Too many bullets incoming, run away from the player while dodging
+    Flee,
+}
+
+/// Per enemy type tuning of the `EnemyAi` transitions.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AiProfile {
+    pub sight_range: f32,
+    pub preferred_distance: f32,
+    pub distance_tolerance: f32,
+    pub dodges: bool,
+    pub flee_threat: usize, // NOTE: dangerous bullets needed to start fleeing
+    pub strafe_switch_time: f32,
+}
+
+impl AiProfile {
+    /// Keeps its distance and shoots, the original pentagon.
+    pub const SHOOTER: AiProfile = AiProfile {
+        sight_range: 1200.,
+        preferred_distance: 350.,
+        distance_tolerance: 60.,
+        dodges: true,
+        flee_threat: 3,
+        strafe_switch_time: 2.5,
+    };
+
+    /// Goes straight for the player, whatever is flying at it.
+    pub const CHARGER: AiProfile = AiProfile {
+        sight_range: 1500.,
+        preferred_distance: 0.,
+        distance_tolerance: 0.,
+        dodges: false,
+        flee_threat: usize::MAX,
+        strafe_switch_time: 2.5,
+    };
+
+    /// Never moves.
+    pub const TURRET: AiProfile = AiProfile {
+        sight_range: 0.,
+        preferred_distance: 0.,
+        distance_tolerance: 0.,
+        dodges: false,
+        flee_threat: usize::MAX,
+        strafe_switch_time: 2.5,
+    };
+
+    /// Like the charger, but from any distance.
+    pub const SWARMER: AiProfile = AiProfile {
+        sight_range: f32::MAX,
+        ..AiProfile::CHARGER
+    };
+}
+
+/// What an enemy is being shot at with.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Threat {
+    pub dangerous_bullets: usize,
+    pub dodge: Vec2,
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct EnemyAi {
+    pub profile: AiProfile,
+    pub state: AiState,
+    strafe_sign: f32,
+    strafe_timer: Timer,
+}
+
+impl EnemyAi {
+    pub fn new(profile: AiProfile) -> Self {
+        Self {
+            profile,
+            state: AiState::Idle,
+            strafe_sign: 1.,
+            strafe_timer: Timer::from_seconds(profile.strafe_switch_time, true),
+        }
+    }
+
+    /// Picks the state for this frame. `distance` is `None` when there is no
+    /// player to react to.
+    pub fn update(&mut self, delta: std::time::Duration, distance: Option<f32>, threat: &Threat) {
+        if self.strafe_timer.tick(delta).just_finished() {
+            self.strafe_sign = -self.strafe_sign;
+        }
+
+        let profile = &self.profile;
+        self.state = if threat.dangerous_bullets >= profile.flee_threat {
+            AiState::Flee
+        } else if threat.dangerous_bullets > 0 && profile.dodges {
+            AiState::Dodge
+        } else {
+            match distance {
+                Some(distance) if distance <= profile.sight_range => {
+                    let (near, far) = (
+                        profile.preferred_distance - profile.distance_tolerance,
+                        profile.preferred_distance + profile.distance_tolerance,
+                    );
+                    // NOTE: inside the tolerance band keep approaching or
+                    // retreating until the preferred distance is reached
+                    match self.state {
+                        _ if distance > far => AiState::Approach,
+                        _ if distance < near => AiState::Retreat,
+                        AiState::Approach if distance > profile.preferred_distance => {
+                            AiState::Approach
+                        }
+                        AiState::Retreat if distance < profile.preferred_distance => {
+                            AiState::Retreat
+                        }
+                        _ => AiState::Strafe,
+                    }
+                }
+                _ => AiState::Idle,
+            }
+        };
+    }
+
+    /// Movement direction for the current state. `to_player` points from the
+    /// enemy to the player.
+    pub fn steering(&self, to_player: Vec2, threat: &Threat) -> Vec2 {
+        let to_player = to_player.normalize_or_zero();
+        match self.state {
+            AiState::Idle => Vec2::ZERO,
+            AiState::Approach => to_player,
+            AiState::Strafe => self.strafe_sign * to_player.perp(),
+            AiState::Retreat => -to_player,
+            AiState::Dodge => threat.dodge,
+            AiState::Flee => (threat.dodge - to_player).normalize_or_zero() * 1.5,
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::bullet::BulletLimits;
+use crate::difficulty::DifficultyScaling;
+use crate::enemy::AimSkill;
+use crate::enemy_ai::AiProfile;
+use crate::game_abilities::BulletEmitter;
+use crate::movement::Handling;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// NOTE: the built-in archetypes are sized and paced relative to these
+pub(crate) const BASE_RADIUS: f32 = 20.;
+pub(crate) const BASE_SPEED: f32 = 80.;
+
+/// Every kind of enemy `spawn_enemy` knows how to build.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum EnemyKind {
+    /// Keeps its distance, dodges and shoots, the original pentagon.
+    Shooter,
+    /// Rushes the player and rams it.
+    Charger,
+    /// Stands still and fires radial bursts.
+    Turret,
+    /// Small and fast, comes in numbers and rams the player.
+    Swarmer,
+    /// Breaks into swarmers when destroyed.
+    Splitter,
+    /// Changes its movement and bullet patterns as it loses health, see `BossPhase`.
+    Boss,
+}
+
+impl EnemyKind {
+    pub const ALL: [EnemyKind; 6] = [
+        EnemyKind::Shooter,
+        EnemyKind::Charger,
+        EnemyKind::Turret,
+        EnemyKind::Swarmer,
+        EnemyKind::Splitter,
+        EnemyKind::Boss,
+    ];
+
+    /// What the kind looks like and does unless the archetype file says otherwise.
+    pub fn built_in_archetype(self) -> EnemyArchetype {
+        match self {
+            EnemyKind::Shooter => EnemyArchetype {
+                sides: 5,
+                radius: BASE_RADIUS,
+                color: Color::RED,
+                speed: BASE_SPEED,
+                health: 1,
+                handling: Handling::ENEMY,
+                ai: AiProfile::SHOOTER,
+                aim: Some(AimSkill::SHARPSHOOTER),
+                shoot_cooldown: Some(0.3),
+                emitter: Some((
+                    BulletEmitter::radial(8)
+                        .with_volleys(2, 0.25, PI / 8.)
+                        .with_limits(BulletLimits {
+                            max_lifetime: None,
+                            max_distance: Some(600.),
+                        }),
+                    3.,
+                )),
+                rams: false,
+                splits_into: None,
+            },
+            EnemyKind::Charger => EnemyArchetype {
+                sides: 4,
+                radius: BASE_RADIUS,
+                color: Color::ORANGE,
+                speed: BASE_SPEED * 2.5,
+                health: 3,
+                handling: Handling {
+                    acceleration: 300.,
+                    friction: 300.,
+                },
+                ai: AiProfile::CHARGER,
+                aim: None,
+                shoot_cooldown: None,
+                emitter: None,
+                rams: true,
+                splits_into: None,
+            },
+            EnemyKind::Turret => EnemyArchetype {
+                sides: 6,
+                radius: BASE_RADIUS * 1.3,
+                color: Color::GRAY,
+                speed: 0.,
+                health: 6,
+                handling: Handling::ENEMY,
+                ai: AiProfile::TURRET,
+                aim: None,
+                shoot_cooldown: None,
+                emitter: Some((
+                    BulletEmitter::spiral(12, PI / 4.)
+                        .with_volleys(3, 0.2, PI / 12.)
+                        .with_limits(BulletLimits {
+                            max_lifetime: None,
+                            max_distance: Some(800.),
+                        }),
+                    2.,
+                )),
+                rams: false,
+                splits_into: None,
+            },
+            EnemyKind::Swarmer => EnemyArchetype {
+                sides: 3,
+                radius: BASE_RADIUS * 0.6,
+                color: Color::YELLOW,
+                speed: BASE_SPEED * 2.,
+                health: 1,
+                handling: Handling {
+                    acceleration: 900.,
+                    friction: 600.,
+                },
+                ai: AiProfile::SWARMER,
+                aim: None,
+                shoot_cooldown: None,
+                emitter: None,
+                rams: true,
+                splits_into: None,
+            },
+            EnemyKind::Splitter => EnemyArchetype {
+                sides: 8,
+                radius: BASE_RADIUS * 1.2,
+                color: Color::GREEN,
+                speed: BASE_SPEED * 0.75,
+                health: 4,
+                handling: Handling::ENEMY,
+                ai: AiProfile::SHOOTER,
+                aim: Some(AimSkill {
+                    leads_target: false,
+                    max_error: 0.2,
+                }),
+                shoot_cooldown: None,
+                emitter: Some((BulletEmitter::aimed_spread(3, PI / 6.), 1.5)),
+                rams: false,
+                splits_into: Some((EnemyKind::Swarmer, 3)),
+            },
+            // NOTE: the phases bring the abilities and the movement
+            EnemyKind::Boss => EnemyArchetype {
+                sides: 12,
+                radius: BASE_RADIUS * 2.5,
+                color: Color::CRIMSON,
+                speed: 0.,
+                health: 60,
+                handling: Handling::ENEMY,
+                ai: AiProfile::TURRET,
+                aim: Some(AimSkill::SHARPSHOOTER),
+                shoot_cooldown: None,
+                emitter: None,
+                rams: false,
+                splits_into: None,
+            },
+        }
+    }
+}
+
+/// Looks, stats and abilities of an `EnemyKind`.
+#[derive(Clone, Debug)]
+pub struct EnemyArchetype {
+    pub sides: usize,
+    pub radius: f32,
+    pub color: Color,
+    pub speed: f32,
+    pub health: u32,
+    pub handling: Handling,
+    pub ai: AiProfile,
+    pub aim: Option<AimSkill>,
+    /// Cooldown of the single aimed shot, if it has one
+    pub shoot_cooldown: Option<f32>,
+    /// Bullet pattern and its cooldown, if it has one
+    pub emitter: Option<(BulletEmitter, f32)>,
+    /// Destroys itself and whatever it runs into
+    pub rams: bool,
+    pub splits_into: Option<(EnemyKind, u32)>,
+}
+
+impl EnemyArchetype {
+    /// The archetype with its stats scaled for the difficulty.
+    pub fn scaled(&self, difficulty: &DifficultyScaling) -> Self {
+        let mut archetype = self.clone();
+        archetype.speed *= difficulty.enemy_speed;
+        archetype.health = difficulty.health(self.health);
+        archetype.shoot_cooldown = self
+            .shoot_cooldown
+            .map(|seconds| difficulty.cooldown(seconds));
+        if let Some((emitter, seconds)) = &mut archetype.emitter {
+            emitter.bullet_speed *= difficulty.bullet_speed;
+            *seconds = difficulty.cooldown(*seconds);
+        }
+        archetype
+    }
+}
+
+/// The archetype `spawn_enemy` builds for every `EnemyKind`.
+pub struct EnemyArchetypes(HashMap<EnemyKind, EnemyArchetype>);
+
+impl Default for EnemyArchetypes {
+    fn default() -> Self {
+        Self(
+            EnemyKind::ALL
+                .iter()
+                .map(|&kind| (kind, kind.built_in_archetype()))
+                .collect(),
+        )
+    }
+}
+
+impl EnemyArchetypes {
+    pub fn get(&self, kind: EnemyKind) -> &EnemyArchetype {
+        &self.0[&kind]
+    }
+
+    pub fn set(&mut self, kind: EnemyKind, archetype: EnemyArchetype) {
+        self.0.insert(kind, archetype);
+    }
+}
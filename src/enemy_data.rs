@@ -0,0 +1,529 @@
+use crate::abilities::Cooldown;
+use crate::boss::Boss;
+use crate::bullet::{BulletBehaviour, BulletLimits};
+use crate::collide::Health;
+use crate::difficulty::DifficultyScaling;
+use crate::enemy::AimSkill;
+use crate::enemy_ai::AiProfile;
+use crate::enemy_archetype::{EnemyArchetype, EnemyArchetypes, EnemyKind};
+use crate::game::{GameState, Speed};
+use crate::game_abilities::ShootAbility;
+use crate::game_abilities::{BulletEmitter, EmittedBehaviour, DEFAULT_BULLET_SPEED};
+use crate::movement::Handling;
+use crate::replay::ReplayMode;
+use crate::waves::{Wave, WaveScript};
+use bevy::asset::{Asset, AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Replaces the built-in `EnemyArchetypes` and `WaveScript` with the ones in
+/// `assets/data/`, so they can be tuned without recompiling. Changes to the
+/// files are picked up while the game runs, the archetypes also by the
+/// enemies already around. See `ChangedFiles` for recorded and replayed runs.
+///
+/// Whatever does not parse or validate is reported on stderr and keeps its
+/// current definition.
+pub struct EnemyDataPlugin;
+
+impl Plugin for EnemyDataPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ArchetypesFile>()
+            .add_asset::<WavesFile>()
+            .init_asset_loader::<RonLoader<ArchetypesFile>>()
+            .init_asset_loader::<RonLoader<WavesFile>>()
+            .add_system(reload_archetypes)
+            .add_system(reload_waves)
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(retune_enemies));
+    }
+}
+
+/// A RON file parsed into `Data`, or the reason it could not be.
+///
+/// NOTE: the loader never fails, a failed asset would keep the game loading forever
+pub(crate) trait RonFile: Asset {
+    type Data: DeserializeOwned;
+    const EXTENSIONS: &'static [&'static str];
+
+    fn new(path: String, data: Result<Self::Data, String>) -> Self;
+}
+
+pub(crate) struct RonLoader<F>(PhantomData<F>);
+
+impl<F> Default for RonLoader<F> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<F: RonFile> AssetLoader for RonLoader<F> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let path = load_context.path().display().to_string();
+            let data = parse_ron(bytes);
+            load_context.set_default_asset(LoadedAsset::new(F::new(path, data)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        F::EXTENSIONS
+    }
+}
+
+fn parse_ron<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    let mut deserializer =
+        ron::de::Deserializer::from_bytes(bytes).map_err(|err| err.to_string())?;
+    let result = T::deserialize(&mut deserializer).and_then(|data| {
+        deserializer.end()?;
+        Ok(data)
+    });
+    result.map_err(|err| {
+        if err.position.line > 0 {
+            return err.to_string();
+        }
+        // NOTE: serde's own errors (unknown fields, variants...) come without a
+        // position, point at where the parser stopped instead
+        let read = bytes.len() - deserializer.remainder().len();
+        let line = bytes[..read].iter().filter(|&&byte| byte == b'\n').count() + 1;
+        format!("line {}: {}", line, err.code)
+    })
+}
+
+#[derive(TypeUuid)]
+#[uuid = "8f6c5a0e-2d4b-4f37-9a51-3c7e1b0d9e42"]
+pub struct ArchetypesFile {
+    path: String,
+    data: Result<HashMap<EnemyKind, ArchetypeDef>, String>,
+}
+
+impl RonFile for ArchetypesFile {
+    type Data = HashMap<EnemyKind, ArchetypeDef>;
+    const EXTENSIONS: &'static [&'static str] = &["archetypes.ron"];
+
+    fn new(path: String, data: Result<Self::Data, String>) -> Self {
+        Self { path, data }
+    }
+}
+
+#[derive(TypeUuid)]
+#[uuid = "b1d7e3c2-6a8f-4e05-8c3d-5f2a9b4e7d10"]
+pub struct WavesFile {
+    path: String,
+    data: Result<Vec<WaveDef>, String>,
+}
+
+impl RonFile for WavesFile {
+    type Data = Vec<WaveDef>;
+    const EXTENSIONS: &'static [&'static str] = &["waves.ron"];
+
+    fn new(path: String, data: Result<Self::Data, String>) -> Self {
+        Self { path, data }
+    }
+}
+
+/// An `EnemyArchetype` as written in the archetype file. Angles are in degrees.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArchetypeDef {
+    sides: usize,
+    radius: f32,
+    color: Color,
+    speed: f32,
+    health: u32,
+    handling: Handling,
+    ai: AiDef,
+    #[serde(default)]
+    aim: Option<AimDef>,
+    #[serde(default)]
+    shoot_cooldown: Option<f32>,
+    #[serde(default)]
+    emitter: Option<EmitterDef>,
+    #[serde(default)]
+    rams: bool,
+    #[serde(default)]
+    splits_into: Option<(EnemyKind, u32)>,
+}
+
+/// One of the `AiProfile` presets, or a profile of its own.
+#[derive(Deserialize)]
+enum AiDef {
+    Shooter,
+    Charger,
+    Turret,
+    Swarmer,
+    Custom(AiProfile),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AimDef {
+    leads_target: bool,
+    max_error: f32,
+}
+
+#[derive(Deserialize)]
+enum PatternDef {
+    Radial,
+    Fan { angle: f32 },
+    Aimed,
+}
+
+/// How the bullets of an emitter move, see `BulletBehaviour`. Angles are in
+/// degrees.
+///
+/// NOTE: an orbit always goes around the shooter, files never name entities
+#[derive(Clone, Copy, Default, Deserialize)]
+enum BehaviourDef {
+    #[default]
+    Straight,
+    Homing {
+        turn_rate: f32,
+    },
+    SineWave {
+        amplitude: f32,
+        frequency: f32,
+    },
+    Accelerating {
+        acceleration: f32,
+        min_speed: f32,
+        max_speed: f32,
+    },
+    OrbitShooter {
+        radius: f32,
+        angular_speed: f32,
+    },
+}
+
+impl From<BehaviourDef> for EmittedBehaviour {
+    fn from(def: BehaviourDef) -> Self {
+        let behaviour = match def {
+            BehaviourDef::Straight => BulletBehaviour::Straight,
+            BehaviourDef::Homing { turn_rate } => BulletBehaviour::Homing {
+                turn_rate: turn_rate.to_radians(),
+            },
+            BehaviourDef::SineWave {
+                amplitude,
+                frequency,
+            } => BulletBehaviour::SineWave {
+                amplitude,
+                frequency,
+            },
+            BehaviourDef::Accelerating {
+                acceleration,
+                min_speed,
+                max_speed,
+            } => BulletBehaviour::Accelerating {
+                acceleration,
+                min_speed,
+                max_speed,
+            },
+            BehaviourDef::OrbitShooter {
+                radius,
+                angular_speed,
+            } => {
+                return EmittedBehaviour::OrbitShooter {
+                    radius,
+                    angular_speed: angular_speed.to_radians(),
+                }
+            }
+        };
+        behaviour.into()
+    }
+}
+
+/// A `BulletEmitter` and its cooldown. Angles are in degrees.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmitterDef {
+    pattern: PatternDef,
+    count: u32,
+    #[serde(default)]
+    spread: f32, // NOTE: ignored by `Radial`, always a full circle
+    #[serde(default)]
+    rotation_speed: f32,
+    #[serde(default)]
+    volleys: Option<(u32, f32, f32)>, // NOTE: count, interval, stagger
+    #[serde(default)]
+    bullet_speed: Option<f32>,
+    #[serde(default)]
+    behaviour: BehaviourDef,
+    #[serde(default)]
+    limits: BulletLimits,
+    cooldown: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WaveDef {
+    #[serde(default)]
+    delay: f32,
+    spawns: Vec<(EnemyKind, (f32, f32))>,
+}
+
+pub(crate) fn check(valid: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+    if valid {
+        Ok(())
+    } else {
+        Err(message())
+    }
+}
+
+impl ArchetypeDef {
+    fn validate(&self, kind: EnemyKind) -> Result<EnemyArchetype, String> {
+        check(self.sides >= 3, || {
+            format!("sides has to be at least 3, not {}", self.sides)
+        })?;
+        check(self.radius > 0., || {
+            format!("radius has to be positive, not {}", self.radius)
+        })?;
+        check(self.speed >= 0., || {
+            format!("speed can not be negative, got {}", self.speed)
+        })?;
+        check(self.health > 0, || {
+            "health has to be at least 1".to_string()
+        })?;
+        if let Some(cooldown) = self.shoot_cooldown {
+            check(cooldown > 0., || {
+                format!("shoot_cooldown has to be positive, not {}", cooldown)
+            })?;
+        }
+        if let Some(aim) = &self.aim {
+            check(aim.max_error >= 0., || {
+                format!("max_error can not be negative, got {}", aim.max_error)
+            })?;
+        }
+        if let Some((split_kind, _)) = self.splits_into {
+            check(split_kind != kind && split_kind != EnemyKind::Boss, || {
+                format!("can not split into {:?}", split_kind)
+            })?;
+        }
+        let emitter = match &self.emitter {
+            Some(emitter) => Some(emitter.validate()?),
+            None => None,
+        };
+
+        Ok(EnemyArchetype {
+            sides: self.sides,
+            radius: self.radius,
+            color: self.color,
+            speed: self.speed,
+            health: self.health,
+            handling: self.handling,
+            ai: match self.ai {
+                AiDef::Shooter => AiProfile::SHOOTER,
+                AiDef::Charger => AiProfile::CHARGER,
+                AiDef::Turret => AiProfile::TURRET,
+                AiDef::Swarmer => AiProfile::SWARMER,
+                AiDef::Custom(profile) => profile,
+            },
+            aim: self.aim.as_ref().map(|aim| AimSkill {
+                leads_target: aim.leads_target,
+                max_error: aim.max_error.to_radians(),
+            }),
+            shoot_cooldown: self.shoot_cooldown,
+            emitter,
+            rams: self.rams,
+            splits_into: self.splits_into,
+        })
+    }
+}
+
+impl EmitterDef {
+    fn validate(&self) -> Result<(BulletEmitter, f32), String> {
+        check(self.count > 0, || {
+            "the emitter needs a count of at least 1".to_string()
+        })?;
+        check(self.cooldown > 0., || {
+            format!(
+                "the emitter cooldown has to be positive, not {}",
+                self.cooldown
+            )
+        })?;
+
+        let spread = self.spread.to_radians();
+        let mut emitter = match self.pattern {
+            PatternDef::Radial => BulletEmitter::radial(self.count),
+            PatternDef::Fan { angle } => BulletEmitter::fan(angle.to_radians(), self.count, spread),
+            PatternDef::Aimed => BulletEmitter::aimed_spread(self.count, spread),
+        };
+        emitter.rotation_speed = self.rotation_speed.to_radians();
+        if let Some((volleys, interval, stagger)) = self.volleys {
+            emitter = emitter.with_volleys(volleys, interval, stagger.to_radians());
+        }
+        let color = emitter.bullet_color;
+        let emitter = emitter
+            .with_bullets(
+                self.bullet_speed.unwrap_or(DEFAULT_BULLET_SPEED),
+                color,
+                self.behaviour,
+            )
+            .with_limits(self.limits);
+        Ok((emitter, self.cooldown))
+    }
+}
+
+impl WaveDef {
+    fn validate(&self) -> Result<Wave, String> {
+        check(self.delay >= 0., || {
+            format!("delay can not be negative, got {}", self.delay)
+        })?;
+        check(!self.spawns.is_empty(), || "nothing spawns".to_string())?;
+        check(
+            self.spawns.iter().all(|(kind, _)| *kind != EnemyKind::Boss),
+            || "the boss comes after the last wave, not in one".to_string(),
+        )?;
+        Ok(Wave {
+            delay: self.delay,
+            spawns: self
+                .spawns
+                .iter()
+                .map(|&(kind, (x, y))| (kind, Vec2::new(x, y)))
+                .collect(),
+        })
+    }
+}
+
+/// The files that were created or changed since the last time.
+///
+/// A recorded or replayed run has to play out with the data it started with,
+/// so while one is going on the changes are held back until it is over.
+#[derive(SystemParam)]
+pub(crate) struct ChangedFiles<'w, 's, F: RonFile> {
+    events: EventReader<'w, 's, AssetEvent<F>>,
+    files: Res<'w, Assets<F>>,
+    mode: Res<'w, ReplayMode>,
+    state: Res<'w, State<GameState>>,
+    held: Local<'s, Vec<Handle<F>>>,
+}
+
+impl<'w, 's, F: RonFile> ChangedFiles<'w, 's, F> {
+    pub(crate) fn read(&mut self) -> Vec<&F> {
+        for event in self.events.iter() {
+            if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+                if !self.held.contains(handle) {
+                    self.held.push(handle.clone());
+                }
+            }
+        }
+        let replaying = !matches!(*self.mode, ReplayMode::Live);
+        if replaying && *self.state.current() == GameState::Playing {
+            return Vec::new();
+        }
+        let files = &self.files;
+        self.held
+            .drain(..)
+            .filter_map(|handle| files.get(handle))
+            .collect()
+    }
+}
+
+fn reload_archetypes(
+    mut files: ChangedFiles<ArchetypesFile>,
+    mut archetypes: ResMut<EnemyArchetypes>,
+) {
+    for file in files.read() {
+        let defs = match &file.data {
+            Ok(defs) => defs,
+            Err(err) => {
+                eprintln!("{}: {}, keeping the current archetypes", file.path, err);
+                continue;
+            }
+        };
+        for kind in EnemyKind::ALL {
+            let def = match defs.get(&kind) {
+                Some(def) => def,
+                None => {
+                    eprintln!("{}: no {:?}, keeping the current one", file.path, kind);
+                    continue;
+                }
+            };
+            match def.validate(kind) {
+                Ok(archetype) => archetypes.set(kind, archetype),
+                Err(err) => eprintln!("{}: {:?} {}, keeping the current one", file.path, kind, err),
+            }
+        }
+    }
+}
+
+fn reload_waves(mut files: ChangedFiles<WavesFile>, mut script: ResMut<WaveScript>) {
+    for file in files.read() {
+        let waves: Result<Vec<Wave>, String> = match &file.data {
+            Ok(defs) => defs
+                .iter()
+                .enumerate()
+                .map(|(i, def)| {
+                    def.validate()
+                        .map_err(|err| format!("wave {}: {}", i + 1, err))
+                })
+                .collect(),
+            Err(err) => Err(err.clone()),
+        };
+        match waves {
+            Ok(waves) => script.waves = waves,
+            Err(err) => eprintln!("{}: {}, keeping the current waves", file.path, err),
+        }
+    }
+}
+
+type RetunedEnemyQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static EnemyKind,
+        &'static mut Speed,
+        &'static mut Handling,
+        &'static mut Health,
+        Option<&'static mut AimSkill>,
+        Option<&'static Children>,
+    ),
+    Without<Boss>,
+>;
+
+/// Applies changed archetypes to the enemies already around. The boss is left
+/// alone, its phases set its numbers.
+fn retune_enemies(
+    archetypes: Res<EnemyArchetypes>,
+    difficulty: Res<DifficultyScaling>,
+    mut q_enemy: RetunedEnemyQuery,
+    mut q_shoot: Query<&mut Cooldown, (With<ShootAbility>, Without<BulletEmitter>)>,
+    mut q_emitter: Query<(&mut BulletEmitter, &mut Cooldown), Without<ShootAbility>>,
+) {
+    if !archetypes.is_changed() {
+        return;
+    }
+    for (kind, mut speed, mut handling, mut health, aim, children) in q_enemy.iter_mut() {
+        let archetype = archetypes.get(*kind).scaled(&difficulty);
+        speed.0 = archetype.speed;
+        *handling = archetype.handling;
+        // NOTE: keeps the hits taken so far
+        let taken = health.max - health.current;
+        health.max = archetype.health;
+        health.current = archetype.health.saturating_sub(taken).max(1);
+        if let (Some(mut aim), Some(skill)) = (aim, archetype.aim) {
+            *aim = skill;
+        }
+
+        for &child in children.iter().flat_map(|children| children.iter()) {
+            if let (Ok(mut cooldown), Some(seconds)) =
+                (q_shoot.get_mut(child), archetype.shoot_cooldown)
+            {
+                cooldown.set_duration(seconds);
+            }
+            if let (Ok((mut emitter, mut cooldown)), Some((new_emitter, seconds))) =
+                (q_emitter.get_mut(child), &archetype.emitter)
+            {
+                *emitter = new_emitter.clone();
+                cooldown.set_duration(*seconds);
+            }
+        }
+    }
+}
@@ -1,12 +1,26 @@
 use crate::abilities::AbilitiesPlugin;
 use crate::actions::*;
+use crate::arena::{Arena, Cursor, WindowArenaPlugin};
+use crate::bomb::BombPlugin;
+use crate::boss::BossPlugin;
 use crate::bullet::*;
+use crate::bullet_time::BulletTimePlugin;
 use crate::collide::CollidePlugin;
+use crate::config::ConfigPlugin;
+use crate::difficulty::DifficultyPlugin;
 use crate::enemy::*;
+use crate::game_over::GameOverPlugin;
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
+use crate::movement::MovementPlugin;
+use crate::pickup::PickupPlugin;
 use crate::player::*;
+use crate::replay::ReplayPlugin;
+use crate::rng::RngPlugin;
+use crate::timestep::{FixedTime, FixedTimestepPlugin};
+use crate::waves::WavePlugin;
 // use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::input::InputPlugin;
 use bevy::prelude::*;
 use bevy_prototype_lyon::plugin::ShapePlugin;
 
@@ -18,6 +32,8 @@ pub enum GameState {
     Playing,
     // Here the menu is drawn and waiting for player interaction
     Menu,
+    // A short look back at the run that just ended, before the menu
+    GameOver,
 }
 
 pub struct GamePlugin;
@@ -26,18 +42,59 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_state(GameState::Loading)
             .add_plugin(ShapePlugin)
+            .add_plugin(WindowArenaPlugin)
             .add_plugin(LoadingPlugin)
             .add_plugin(MenuPlugin)
+            .add_plugin(GameOverPlugin)
+            .add_plugin(GameplayPlugin);
+        // .add_plugin(LogDiagnosticsPlugin::default())
+        // .add_plugin(FrameTimeDiagnosticsPlugin::default());
+    }
+}
+
+/// Runs the game without a window, renderer or menus, straight into
+/// `GameState::Playing`, one fixed step per update. Meant to be added after
+/// `MinimalPlugins`, e.g. for integration tests; the arena and the cursor are
+/// virtual and can be moved through the `Arena` and `Cursor` resources.
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(GameState::Playing)
+            // NOTE: gives the input manager something to read (and to mock)
+            .add_plugin(InputPlugin)
+            .init_resource::<Arena>()
+            .init_resource::<Cursor>()
+            .insert_resource(FixedTime::default().with_lockstep())
+            .add_plugin(GameplayPlugin);
+    }
+}
+
+/// The gameplay itself, shared by the windowed and the headless game.
+struct GameplayPlugin;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        // NOTE: first, the other plugins add their systems to its stage
+        app.add_plugin(FixedTimestepPlugin)
             .add_plugin(PlayerPlugin)
             .add_plugin(ActionsPlugin)
             .add_plugin(BulletPlugin)
             .add_plugin(CollidePlugin)
             .add_plugin(EnemyPlugin)
+            .add_plugin(BossPlugin)
+            .add_plugin(WavePlugin)
+            .add_plugin(DifficultyPlugin)
+            .add_plugin(PickupPlugin)
+            .add_plugin(BombPlugin)
+            .add_plugin(BulletTimePlugin)
+            .add_plugin(MovementPlugin)
             .add_plugin(AbilitiesPlugin)
+            .add_plugin(RngPlugin)
+            .add_plugin(ConfigPlugin)
+            .add_plugin(ReplayPlugin)
             .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
             .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(destroy_entities));
-        // .add_plugin(LogDiagnosticsPlugin::default())
-        // .add_plugin(FrameTimeDiagnosticsPlugin::default());
     }
 }
 
@@ -47,10 +104,6 @@ struct MainCamera;
 #[derive(Component)]
 pub struct Speed(pub f32);
 
-pub const BASE_SPEED: f32 = 80.;
-pub const BASE_RADIUS: f32 = 20.;
-pub const BULLET_SPEED: f32 = 250.0; // NOTE: points per seccond
-
 fn setup(mut commands: Commands) {
     commands
         .spawn_bundle(OrthographicCameraBundle::new_2d())
@@ -0,0 +1,59 @@
+use crate::abilities::*;
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub struct BombAbility {
+    pub radius: f32,
+    /// Invulnerability the player gets from a bomb, in seconds
+    pub grace_time: f32,
+    /// Health it takes off every enemy in reach
+    pub damage: u32,
+}
+
+#[derive(Bundle)]
+pub struct BombAbilityBundle {
+    pub marker: BombAbility,
+    pub cooldown: Cooldown,
+    pub stock: BombStock,
+}
+
+/// Bombs left, and the kills made towards the next one.
+#[derive(Component)]
+pub struct BombStock {
+    pub bombs: u32,
+    pub max: u32,
+    pub kills_per_bomb: u32,
+    kills: u32,
+}
+
+impl BombStock {
+    pub fn new(max: u32, kills_per_bomb: u32) -> Self {
+        Self {
+            bombs: max,
+            max,
+            kills_per_bomb,
+            kills: 0,
+        }
+    }
+
+    /// Uses up a bomb, if there is one left.
+    pub fn take(&mut self) -> bool {
+        if self.bombs == 0 {
+            return false;
+        }
+        self.bombs -= 1;
+        true
+    }
+
+    /// Every `kills_per_bomb` kills give back a bomb, up to the `max`.
+    pub fn count_kill(&mut self) {
+        if self.bombs >= self.max {
+            return;
+        }
+        self.kills += 1;
+        if self.kills >= self.kills_per_bomb {
+            self.kills = 0;
+            self.bombs += 1;
+        }
+    }
+}
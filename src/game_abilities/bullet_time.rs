@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+/// Slows the world down while the player keeps going at full speed.
+///
+/// NOTE: has no `Cooldown`, the `BulletTimeMeter` limits it instead
+#[derive(Component)]
+pub struct BulletTimeAbility {
+    /// `TimeScale` of the world while active
+    pub time_scale: f32,
+    /// Seconds of bullet time the meter gains back per second
+    pub refill_rate: f32,
+}
+
+/// Seconds of bullet time left, drained while active.
+#[derive(Component)]
+pub struct BulletTimeMeter {
+    pub seconds: f32,
+    pub max: f32,
+    pub active: bool,
+}
+
+impl BulletTimeMeter {
+    pub fn new(max: f32) -> Self {
+        Self {
+            seconds: max,
+            max,
+            active: false,
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.seconds / self.max
+    }
+
+    /// Drains the meter while active, turning it off once empty, and refills
+    /// it otherwise.
+    pub fn tick(&mut self, delta: f32, refill_rate: f32) {
+        if self.active {
+            self.seconds = (self.seconds - delta).max(0.);
+            self.active = self.seconds > 0.;
+        } else {
+            self.seconds = (self.seconds + refill_rate * delta).min(self.max);
+        }
+    }
+}
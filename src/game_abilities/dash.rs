@@ -2,10 +2,33 @@ use crate::abilities::*;
 use bevy::prelude::*;
 
 #[derive(Component)]
-pub struct DashAbility;
+pub struct DashAbility {
+    pub distance: f32,
+    pub duration: f32, // NOTE: seconds
+}
 
 #[derive(Bundle)]
 pub struct DashAbilityBundle {
     pub marker: DashAbility, //marker,
     pub cooldown: Cooldown,
 }
+
+/// Present on an entity while it is dashing.
+#[derive(Component)]
+pub struct Dashing {
+    pub direction: Vec2,
+    pub speed: f32,
+    pub timer: Timer,
+    pub trail_timer: Timer,
+}
+
+impl Dashing {
+    pub fn new(direction: Vec2, dash: &DashAbility) -> Self {
+        Self {
+            direction,
+            speed: dash.distance / dash.duration,
+            timer: Timer::from_seconds(dash.duration, false),
+            trail_timer: Timer::from_seconds(0.02, true),
+        }
+    }
+}
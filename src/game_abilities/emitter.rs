@@ -0,0 +1,199 @@
+use crate::abilities::*;
+use crate::bullet::{BulletBehaviour, BulletLimits};
+use bevy::prelude::*;
+use std::f32::consts::PI;
+use std::ops::Range;
+
+/// NOTE: points per second, unless set with `with_bullets`
+pub(crate) const DEFAULT_BULLET_SPEED: f32 = 250.;
+
+/// Shape of a single volley fired by a `BulletEmitter`.
+#[derive(Clone, Copy, Debug)]
+pub enum BulletPattern {
+    /// Bullets evenly spaced over a full circle.
+    Radial,
+    /// Bullets spread over `spread` radians around a fixed angle.
+    Fan { angle: f32 },
+    /// Bullets spread over `spread` radians around the player.
+    Aimed,
+}
+
+/// How the bullets of a `BulletEmitter` move once fired.
+///
+/// Unlike a `BulletBehaviour` it names no entity: an orbit is bound to
+/// whoever fires the bullet, when it is fired.
+#[derive(Clone, Copy, Debug)]
+pub enum EmittedBehaviour {
+    Fixed(BulletBehaviour),
+    /// Circles the shooter at `radius` points, `angular_speed` radians per second
+    OrbitShooter {
+        radius: f32,
+        angular_speed: f32,
+    },
+}
+
+impl EmittedBehaviour {
+    pub fn bind(self, shooter: Entity) -> BulletBehaviour {
+        match self {
+            EmittedBehaviour::Fixed(behaviour) => behaviour,
+            EmittedBehaviour::OrbitShooter {
+                radius,
+                angular_speed,
+            } => BulletBehaviour::Orbit {
+                center: shooter,
+                radius,
+                angular_speed,
+            },
+        }
+    }
+}
+
+impl From<BulletBehaviour> for EmittedBehaviour {
+    fn from(behaviour: BulletBehaviour) -> Self {
+        EmittedBehaviour::Fixed(behaviour)
+    }
+}
+
+/// Fires volleys of bullets following a `BulletPattern` every time its
+/// `Cooldown` is finished.
+///
+/// A non-zero `rotation_speed` turns the whole pattern over time (a radial
+/// emitter becomes a spiral), and `volleys` > 1 fires a staggered burst of
+/// volleys `volley_interval` seconds apart, each one rotated by `stagger`.
+#[derive(Component, Clone, Debug)]
+pub struct BulletEmitter {
+    pub pattern: BulletPattern,
+    pub count: u32,
+    pub spread: f32,
+    pub rotation_speed: f32, // NOTE: radians per second
+    pub volleys: u32,
+    pub volley_interval: f32,
+    pub stagger: f32,
+    pub bullet_speed: f32,
+    pub bullet_color: Color,
+    pub bullet_behaviour: EmittedBehaviour,
+    pub bullet_limits: BulletLimits,
+    rotation: f32,
+    volleys_left: u32,
+    next_volley_in: f32,
+}
+
+impl BulletEmitter {
+    fn new(pattern: BulletPattern, count: u32, spread: f32) -> Self {
+        Self {
+            pattern,
+            count,
+            spread,
+            rotation_speed: 0.,
+            volleys: 1,
+            volley_interval: 0.,
+            stagger: 0.,
+            bullet_speed: DEFAULT_BULLET_SPEED,
+            bullet_color: Color::ORANGE_RED,
+            bullet_behaviour: BulletBehaviour::Straight.into(),
+            bullet_limits: BulletLimits::default(),
+            rotation: 0.,
+            volleys_left: 0,
+            next_volley_in: 0.,
+        }
+    }
+
+    pub fn radial(count: u32) -> Self {
+        Self::new(BulletPattern::Radial, count, 2. * PI)
+    }
+
+    pub fn spiral(count: u32, rotation_speed: f32) -> Self {
+        Self {
+            rotation_speed,
+            ..Self::radial(count)
+        }
+    }
+
+    pub fn fan(angle: f32, count: u32, spread: f32) -> Self {
+        Self::new(BulletPattern::Fan { angle }, count, spread)
+    }
+
+    pub fn aimed_spread(count: u32, spread: f32) -> Self {
+        Self::new(BulletPattern::Aimed, count, spread)
+    }
+
+    pub fn with_volleys(mut self, volleys: u32, volley_interval: f32, stagger: f32) -> Self {
+        self.volleys = volleys.max(1);
+        self.volley_interval = volley_interval;
+        self.stagger = stagger;
+        self
+    }
+
+    pub fn with_bullets(
+        mut self,
+        speed: f32,
+        color: Color,
+        behaviour: impl Into<EmittedBehaviour>,
+    ) -> Self {
+        self.bullet_speed = speed;
+        self.bullet_color = color;
+        self.bullet_behaviour = behaviour.into();
+        self
+    }
+
+    pub fn with_limits(mut self, limits: BulletLimits) -> Self {
+        self.bullet_limits = limits;
+        self
+    }
+
+    /// Advances the emitter by `delta` seconds and returns the indices (within
+    /// the current burst) of the volleys that have to be fired this frame.
+    /// `cooldown` is restarted on every burst.
+    pub fn tick(&mut self, delta: f32, cooldown: &mut Cooldown) -> Range<u32> {
+        self.rotation = (self.rotation + self.rotation_speed * delta) % (2. * PI);
+        if self.volleys_left == 0 {
+            if !cooldown.finished() {
+                return 0..0;
+            }
+            cooldown.start();
+            self.volleys_left = self.volleys;
+            self.next_volley_in = 0.;
+        } else {
+            self.next_volley_in -= delta;
+        }
+
+        let first = self.volleys - self.volleys_left;
+        while self.volleys_left > 0 && self.next_volley_in <= 0. {
+            self.volleys_left -= 1;
+            self.next_volley_in += self.volley_interval;
+        }
+        first..self.volleys - self.volleys_left
+    }
+
+    /// Bullet angles of the given volley of a burst. `aim` is the angle
+    /// towards the player and is only used by `BulletPattern::Aimed`.
+    pub fn volley_angles(&self, volley: u32, aim: f32) -> Vec<f32> {
+        let base = match self.pattern {
+            BulletPattern::Radial => 0.,
+            BulletPattern::Fan { angle } => angle,
+            BulletPattern::Aimed => aim,
+        } + self.rotation
+            + self.stagger * volley as f32;
+
+        let count = self.count.max(1);
+        if count == 1 {
+            return vec![base];
+        }
+        // A full circle must not put the first and last bullet on top of each other
+        let step = match self.pattern {
+            BulletPattern::Radial => self.spread / count as f32,
+            _ => self.spread / (count - 1) as f32,
+        };
+        let start = match self.pattern {
+            BulletPattern::Radial => base,
+            _ => base - self.spread / 2.,
+        };
+        (0..count).map(|i| start + step * i as f32).collect()
+    }
+}
+
+#[derive(Bundle)]
+pub struct BulletEmitterBundle {
+    pub emitter: BulletEmitter,
+    pub cooldown: Cooldown,
+}
@@ -1,5 +1,15 @@
+mod bomb;
+mod bullet_time;
 mod dash;
+mod emitter;
+mod shield;
 mod shoot;
+mod weapon;
 
+pub use bomb::*;
+pub use bullet_time::*;
 pub use dash::*;
+pub use emitter::*;
+pub use shield::*;
 pub use shoot::*;
+pub use weapon::*;
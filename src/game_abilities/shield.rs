@@ -0,0 +1,35 @@
+use crate::abilities::*;
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub struct ShieldAbility {
+    pub parry_window: f32, // NOTE: seconds
+    pub block_duration: f32,
+}
+
+#[derive(Bundle)]
+pub struct ShieldAbilityBundle {
+    pub marker: ShieldAbility,
+    pub cooldown: Cooldown,
+}
+
+/// Present on an entity while its shield is up. Enemy bullets that touch the
+/// shield during the parry window are reflected, later ones are absorbed.
+#[derive(Component)]
+pub struct Shielding {
+    pub parry_window: f32,
+    pub timer: Timer,
+}
+
+impl Shielding {
+    pub fn new(shield: &ShieldAbility) -> Self {
+        Self {
+            parry_window: shield.parry_window,
+            timer: Timer::from_seconds(shield.block_duration, false),
+        }
+    }
+
+    pub fn is_parrying(&self) -> bool {
+        self.timer.elapsed_secs() < self.parry_window
+    }
+}
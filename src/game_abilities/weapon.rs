@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+
+/// The guns the player can switch between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeaponKind {
+    Pistol,
+    /// A wide spread of slow pellets
+    Shotgun,
+    /// Small bullets, and lots of them
+    MachineGun,
+    /// Held down to charge a bullet that goes through a few enemies, bigger,
+    /// faster and harder hitting the longer it charged
+    ChargedShot,
+    /// A beam across the arena that hits everything along the aim at once
+    Laser,
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 5] = [
+        WeaponKind::Pistol,
+        WeaponKind::Shotgun,
+        WeaponKind::MachineGun,
+        WeaponKind::ChargedShot,
+        WeaponKind::Laser,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WeaponKind::Pistol => "Pistol",
+            WeaponKind::Shotgun => "Shotgun",
+            WeaponKind::MachineGun => "Machine gun",
+            WeaponKind::ChargedShot => "Charged shot",
+            WeaponKind::Laser => "Laser",
+        }
+    }
+
+    /// The weapon `offset` places further in `ALL`, going around at the ends.
+    pub fn cycled(self, offset: isize) -> Self {
+        let index = Self::ALL.iter().position(|&kind| kind == self).unwrap() as isize;
+        let len = Self::ALL.len() as isize;
+        Self::ALL[(index + offset).rem_euclid(len) as usize]
+    }
+
+    pub fn stats(self) -> WeaponStats {
+        match self {
+            WeaponKind::Pistol => WeaponStats {
+                cooldown: 1.,
+                bullet_speed: 1.,
+                bullet_radius: 1.,
+                color: Color::ORANGE,
+                bullets: 1,
+                spread: 0.,
+                pierce: 0,
+                damage: 1,
+                charge_time: None,
+                cancels_bullets: false,
+                beam: false,
+            },
+            WeaponKind::Shotgun => WeaponStats {
+                cooldown: 3.,
+                bullet_speed: 0.9,
+                bullet_radius: 0.75,
+                color: Color::YELLOW,
+                bullets: 6,
+                spread: 0.6,
+                pierce: 0,
+                damage: 1,
+                charge_time: None,
+                cancels_bullets: false,
+                beam: false,
+            },
+            WeaponKind::MachineGun => WeaponStats {
+                cooldown: 0.3,
+                bullet_speed: 1.3,
+                bullet_radius: 0.6,
+                color: Color::GOLD,
+                bullets: 1,
+                spread: 0.,
+                pierce: 0,
+                damage: 1,
+                charge_time: None,
+                cancels_bullets: false,
+                beam: false,
+            },
+            WeaponKind::ChargedShot => WeaponStats {
+                cooldown: 1.,
+                bullet_speed: 0.7,
+                bullet_radius: 1.5,
+                color: Color::FUCHSIA,
+                bullets: 1,
+                spread: 0.,
+                pierce: 3,
+                damage: 1,
+                charge_time: Some(1.5),
+                cancels_bullets: true,
+                beam: false,
+            },
+            WeaponKind::Laser => WeaponStats {
+                cooldown: 0.15,
+                bullet_speed: 0.,
+                bullet_radius: 0.5,
+                color: Color::VIOLET,
+                bullets: 1,
+                spread: 0.,
+                pierce: u32::MAX,
+                damage: 1,
+                charge_time: None,
+                cancels_bullets: false,
+                beam: true,
+            },
+        }
+    }
+}
+
+/// What a weapon fires. Cooldown, bullet speed and radius are factors of the
+/// `GameConfig` ones, so the config tunes every weapon at once.
+#[derive(Clone, Copy, Debug)]
+pub struct WeaponStats {
+    pub cooldown: f32,
+    pub bullet_speed: f32,
+    pub bullet_radius: f32,
+    pub color: Color,
+    pub bullets: u32,
+    /// Radians between the outermost bullets of a shot
+    pub spread: f32,
+    /// Enemies a bullet goes through before it is used up
+    pub pierce: u32,
+    /// Health a bullet takes off what it hits
+    pub damage: u32,
+    /// Seconds to charge a shot fully, fired when the action is let go.
+    /// Without one the weapon fires for as long as the action is held.
+    pub charge_time: Option<f32>,
+    /// Whether its bullets erase the enemy bullets they touch
+    pub cancels_bullets: bool,
+    /// Whether it fires a beam instead of bullets. The bullet radius sets
+    /// how wide it is, and its pierce how many things it hits.
+    pub beam: bool,
+}
+
+impl WeaponStats {
+    /// How far along a charge of `held` seconds is, from 0 to 1.
+    pub fn charge(&self, held: f32) -> f32 {
+        self.charge_time
+            .map_or(0., |charge_time| (held / charge_time).min(1.))
+    }
+
+    /// The stats of a shot charged for `held` seconds.
+    pub fn charged(mut self, held: f32) -> Self {
+        let charge = self.charge(held);
+        self.bullet_radius *= 1. + 1.5 * charge;
+        self.bullet_speed *= 1. + charge;
+        self.damage += (3. * charge).round() as u32;
+        self
+    }
+
+    /// Angles of the bullets of one shot towards `aim`.
+    pub fn angles(&self, aim: f32) -> Vec<f32> {
+        if self.bullets <= 1 {
+            return vec![aim];
+        }
+        let step = self.spread / (self.bullets - 1) as f32;
+        (0..self.bullets)
+            .map(|i| aim - self.spread / 2. + i as f32 * step)
+            .collect()
+    }
+}
+
+/// The weapon the `ShootAbility` it sits on fires.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct EquippedWeapon(pub WeaponKind);
+
+/// A shot of a charging weapon on its way.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct ShotCharge {
+    /// NOTE: only counts while the cooldown is finished
+    pub seconds: f32,
+    /// Aim of a shot let go of during the cooldown, fired once it is over
+    pub released: Option<f32>,
+}
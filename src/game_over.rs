@@ -0,0 +1,97 @@
+use crate::boss::BossEncounter;
+use crate::game::GameState;
+use crate::loading::FontAssets;
+use crate::rng::GameRng;
+use bevy::prelude::*;
+
+/// Seconds before going back to the menu, unless clicked away sooner
+const GAME_OVER_SECONDS: f32 = 4.;
+
+/// Shows that the run is over, won or lost, and its seed so it can be played
+/// again, for a moment before the menu comes back.
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(setup_game_over))
+            .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(leave_game_over))
+            .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(despawn_game_over));
+    }
+}
+
+/// Everything shown on the game over screen, its camera included.
+#[derive(Component)]
+struct GameOverScreen;
+
+#[derive(Component)]
+struct GameOverTimer(Timer);
+
+fn setup_game_over(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    rng: Res<GameRng>,
+    encounter: Res<BossEncounter>,
+) {
+    let title = if *encounter == BossEncounter::Defeated {
+        "Victory"
+    } else {
+        "Game over"
+    };
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(GameOverScreen);
+    let style = |font_size| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(GameOverScreen)
+        .insert(GameOverTimer(Timer::from_seconds(GAME_OVER_SECONDS, false)))
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(title, style(60.0), Default::default()),
+                ..Default::default()
+            });
+            if let Some(seed) = rng.seed() {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        format!("Seed: {}", seed),
+                        style(24.0),
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+fn leave_game_over(
+    time: Res<Time>,
+    mouse: Res<Input<MouseButton>>,
+    mut state: ResMut<State<GameState>>,
+    mut q_timer: Query<&mut GameOverTimer>,
+) {
+    for mut timer in q_timer.iter_mut() {
+        if timer.0.tick(time.delta()).finished() || mouse.just_pressed(MouseButton::Left) {
+            state.set(GameState::Menu).unwrap();
+        }
+    }
+}
+
+fn despawn_game_over(mut commands: Commands, query: Query<Entity, With<GameOverScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,32 @@
+mod abilities;
+mod actions;
+pub mod arena;
+mod bomb;
+pub mod boss;
+pub mod bullet;
+pub mod bullet_pool;
+mod bullet_time;
+mod collide;
+pub mod config;
+pub mod difficulty;
+mod direction;
+pub mod enemy;
+mod enemy_ai;
+pub mod enemy_archetype;
+mod enemy_data;
+pub mod game;
+mod game_abilities;
+mod game_over;
+mod loading;
+mod menu;
+mod movement;
+pub mod pickup;
+pub mod player;
+pub mod replay;
+pub mod rng;
+pub mod timestep;
+mod utils;
+pub mod waves;
+
+pub use game::{GamePlugin, HeadlessPlugin};
+pub use game_abilities::WeaponKind;
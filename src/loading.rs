@@ -1,3 +1,5 @@
+use crate::config::{ConfigFile, ConfigFilePlugin};
+use crate::enemy_data::{ArchetypesFile, EnemyDataPlugin, WavesFile};
 use crate::game::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::{AssetCollection, AssetLoader};
@@ -6,8 +8,13 @@ pub struct LoadingPlugin;
 
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
+        // NOTE: registers the loaders of the data files
+        app.add_plugin(EnemyDataPlugin)
+            .add_plugin(ConfigFilePlugin)
+            .add_startup_system(watch_data_files);
         AssetLoader::new(GameState::Loading)
             .with_collection::<FontAssets>()
+            .with_collection::<DataAssets>()
             // .with_collection::<AudioAssets>()
             // .with_collection::<TextureAssets>()
             .continue_to_state(GameState::Menu)
@@ -20,3 +27,20 @@ pub struct FontAssets {
     #[asset(path = "fonts/FiraSans-Bold.ttf")]
     pub fira_sans: Handle<Font>,
 }
+
+#[allow(dead_code)] // NOTE: only kept around so the files stay loaded, and watched
+#[derive(AssetCollection)]
+pub struct DataAssets {
+    #[asset(path = "data/enemies.archetypes.ron")]
+    pub archetypes: Handle<ArchetypesFile>,
+    #[asset(path = "data/default.waves.ron")]
+    pub waves: Handle<WavesFile>,
+    #[asset(path = "data/game.config.ron")]
+    pub config: Handle<ConfigFile>,
+}
+
+fn watch_data_files(asset_server: Res<AssetServer>) {
+    if let Err(err) = asset_server.watch_for_changes() {
+        eprintln!("Changes to the data files will need a restart: {:?}", err);
+    }
+}
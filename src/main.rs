@@ -1,21 +1,13 @@
-mod abilities;
-mod actions;
-mod bullet;
-mod collide;
-mod direction;
-mod enemy;
-mod game;
-mod game_abilities;
-mod loading;
-mod menu;
-mod player;
-mod utils;
-
-use bevy::{prelude::*, window::WindowMode};
-use game::GamePlugin;
+use bevy::prelude::*;
 
+#[cfg(feature = "windowed")]
 fn main() {
+    use bevy::window::WindowMode;
+    use gameing::GamePlugin;
+
     App::new()
+        .insert_resource(replay_mode())
+        .insert_resource(config_overrides())
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(ClearColor(Color::rgb(0., 0.1, 0.3)))
         .insert_resource(WindowDescriptor {
@@ -27,3 +19,42 @@ fn main() {
         .add_plugin(GamePlugin)
         .run();
 }
+
+/// Without the `windowed` feature the game simulates a single run without a
+/// window, e.g. on CI machines without a GPU.
+#[cfg(not(feature = "windowed"))]
+fn main() {
+    use gameing::{game::GameState, HeadlessPlugin};
+
+    fn exit_when_run_is_over(mut exit: EventWriter<bevy::app::AppExit>) {
+        exit.send(bevy::app::AppExit);
+    }
+
+    App::new()
+        .insert_resource(replay_mode())
+        .insert_resource(config_overrides())
+        .add_plugins(MinimalPlugins)
+        .add_plugin(HeadlessPlugin)
+        .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(exit_when_run_is_over))
+        // NOTE: a replay that cannot be played goes back to the menu instead
+        .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(exit_when_run_is_over))
+        .run();
+}
+
+fn replay_mode() -> gameing::replay::ReplayMode {
+    use gameing::replay::ReplayMode;
+
+    ReplayMode::from_args(std::env::args()).unwrap_or_else(|err| {
+        eprintln!("Ignoring the replay arguments: {}", err);
+        ReplayMode::Live
+    })
+}
+
+fn config_overrides() -> gameing::config::ConfigOverrides {
+    use gameing::config::ConfigOverrides;
+
+    ConfigOverrides::from_args(std::env::args()).unwrap_or_else(|err| {
+        eprintln!("Ignoring the config arguments: {}", err);
+        ConfigOverrides::default()
+    })
+}
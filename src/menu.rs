@@ -1,5 +1,7 @@
+use crate::difficulty::DifficultySettings;
 use crate::game::GameState;
 use crate::loading::FontAssets;
+use bevy::ecs::system::EntityCommands;
 use bevy::prelude::*;
 
 pub struct MenuPlugin;
@@ -8,7 +10,13 @@ impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonColors>()
             .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup_menu))
-            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(click_play_button));
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(highlight_buttons)
+                    .with_system(click_play_button)
+                    .with_system(click_difficulty_buttons),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(despawn_menu));
     }
 }
 
@@ -25,69 +33,162 @@ impl Default for ButtonColors {
         }
     }
 }
+#[derive(Component)]
+struct MenuRoot;
+
 #[derive(Component)]
 struct PlayButton;
 
+/// Goes through the `Difficulty` presets.
+#[derive(Component)]
+struct DifficultyButton;
+
+/// Turns the dynamic difficulty on and off.
+#[derive(Component)]
+struct DynamicButton;
+
 fn setup_menu(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     button_colors: Res<ButtonColors>,
+    settings: Res<DifficultySettings>,
 ) {
     commands.spawn_bundle(UiCameraBundle::default());
     commands
-        .spawn_bundle(ButtonBundle {
+        .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Px(120.0), Val::Px(50.0)),
-                margin: Rect::all(Val::Auto),
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
                 ..Default::default()
             },
-            color: button_colors.normal,
+            color: Color::NONE.into(),
             ..Default::default()
         })
-        .insert(PlayButton)
+        .insert(MenuRoot)
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle {
-                text: Text {
-                    sections: vec![TextSection {
-                        value: "Play".to_string(),
-                        style: TextStyle {
-                            font: font_assets.fira_sans.clone(),
-                            font_size: 40.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                        },
-                    }],
-                    alignment: Default::default(),
-                },
-                ..Default::default()
-            });
+            let font = font_assets.fira_sans.clone();
+            spawn_button(parent, &button_colors, font.clone(), "Play", 40.0).insert(PlayButton);
+            spawn_button(
+                parent,
+                &button_colors,
+                font.clone(),
+                &difficulty_label(&settings),
+                24.0,
+            )
+            .insert(DifficultyButton);
+            spawn_button(
+                parent,
+                &button_colors,
+                font,
+                &dynamic_label(&settings),
+                24.0,
+            )
+            .insert(DynamicButton);
+        });
+}
+
+fn spawn_button<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    button_colors: &ButtonColors,
+    font: Handle<Font>,
+    label: &str,
+    font_size: f32,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut button = parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(260.0), Val::Px(50.0)),
+            margin: Rect::all(Val::Px(8.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: button_colors.normal,
+        ..Default::default()
+    });
+    button.with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: label.to_string(),
+                    style: TextStyle {
+                        font,
+                        font_size,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                }],
+                alignment: Default::default(),
+            },
+            ..Default::default()
         });
+    });
+    button
 }
 
-type ButtonInteraction<'a> = (Entity, &'a Interaction, &'a mut UiColor, &'a Children);
+fn difficulty_label(settings: &DifficultySettings) -> String {
+    format!("Difficulty: {}", settings.preset.name())
+}
 
-fn click_play_button(
-    mut commands: Commands,
+fn dynamic_label(settings: &DifficultySettings) -> String {
+    let state = if settings.dynamic { "On" } else { "Off" };
+    format!("Dynamic: {}", state)
+}
+
+type ButtonInteraction<'a> = (&'a Interaction, &'a mut UiColor);
+
+fn highlight_buttons(
     button_colors: Res<ButtonColors>,
-    mut state: ResMut<State<GameState>>,
     mut interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<Button>)>,
-    text_query: Query<Entity, With<Text>>,
 ) {
-    for (button, interaction, mut color, children) in interaction_query.iter_mut() {
-        let text = text_query.get(children[0]).unwrap();
+    for (interaction, mut color) in interaction_query.iter_mut() {
         match *interaction {
-            Interaction::Clicked => {
-                commands.entity(button).despawn();
-                commands.entity(text).despawn();
-                state.set(GameState::Playing).unwrap();
-            }
             Interaction::Hovered => {
                 *color = button_colors.hovered;
             }
-            Interaction::None => {
+            Interaction::None | Interaction::Clicked => {
                 *color = button_colors.normal;
             }
         }
     }
 }
+
+fn click_play_button(
+    mut state: ResMut<State<GameState>>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<PlayButton>)>,
+) {
+    for interaction in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            state.set(GameState::Playing).unwrap();
+        }
+    }
+}
+
+type SettingsButtonQuery<'w, 's, T> =
+    Query<'w, 's, (&'static Interaction, &'static Children), (Changed<Interaction>, With<T>)>;
+
+fn click_difficulty_buttons(
+    mut settings: ResMut<DifficultySettings>,
+    q_difficulty: SettingsButtonQuery<DifficultyButton>,
+    q_dynamic: SettingsButtonQuery<DynamicButton>,
+    mut q_text: Query<&mut Text>,
+) {
+    for (interaction, children) in q_difficulty.iter() {
+        if *interaction == Interaction::Clicked {
+            settings.preset = settings.preset.next();
+            q_text.get_mut(children[0]).unwrap().sections[0].value = difficulty_label(&settings);
+        }
+    }
+    for (interaction, children) in q_dynamic.iter() {
+        if *interaction == Interaction::Clicked {
+            settings.dynamic = !settings.dynamic;
+            q_text.get_mut(children[0]).unwrap().sections[0].value = dynamic_label(&settings);
+        }
+    }
+}
+
+fn despawn_menu(mut commands: Commands, query: Query<Entity, With<MenuRoot>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
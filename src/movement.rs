@@ -0,0 +1,103 @@
+use crate::game::Speed;
+use crate::timestep::{FixedTime, FixedUpdateStage, IgnoresTimeScale, TimeScale};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(
+            FixedUpdateStage,
+            SystemSet::new()
+                .with_system(
+                    accelerate
+                        .label("accelerate")
+                        .after("action")
+                        .after("aiming"),
+                )
+                .with_system(
+                    apply_velocity
+                        .label("velocity")
+                        .after("accelerate")
+                        .after("bullet_movement"),
+                ),
+        );
+    }
+}
+
+/// Points per second, integrated into the `Transform` every frame.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Velocity(pub Vec2);
+
+/// Where an entity wants to go. Its length scales the `Speed` it wants to go
+/// at, so `Vec2::ZERO` means "stop".
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Thrust(pub Vec2);
+
+/// How fast an entity with `Thrust` gets up to its `Speed`, and how fast it
+/// stops once it lets go.
+#[derive(Component, Clone, Copy, Debug, Deserialize)]
+pub struct Handling {
+    pub acceleration: f32, // NOTE: points per second squared
+    pub friction: f32,
+}
+
+impl Handling {
+    pub const PLAYER: Handling = Handling {
+        acceleration: 900.,
+        friction: 700.,
+    };
+
+    pub const ENEMY: Handling = Handling {
+        acceleration: 600.,
+        friction: 400.,
+    };
+}
+
+#[derive(Bundle, Default)]
+pub struct MovementBundle {
+    pub velocity: Velocity,
+    pub thrust: Thrust,
+}
+
+type AcceleratingQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Velocity,
+        &'static Thrust,
+        &'static Handling,
+        &'static Speed,
+        Option<&'static IgnoresTimeScale>,
+    ),
+>;
+
+fn accelerate(time: Res<FixedTime>, time_scale: Res<TimeScale>, mut query: AcceleratingQuery) {
+    for (mut velocity, thrust, handling, speed, ignores) in query.iter_mut() {
+        let target = thrust.0 * speed.0;
+        let rate = if thrust.0 == Vec2::ZERO {
+            handling.friction
+        } else {
+            handling.acceleration
+        };
+        let step = rate * time_scale.delta_seconds_for(&time, ignores);
+        let difference = target - velocity.0;
+        if difference.length() <= step {
+            velocity.0 = target;
+        } else {
+            velocity.0 += difference.normalize() * step;
+        }
+    }
+}
+
+fn apply_velocity(
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<(&mut Transform, &Velocity, Option<&IgnoresTimeScale>)>,
+) {
+    for (mut transform, velocity, ignores) in query.iter_mut() {
+        let delta = time_scale.delta_seconds_for(&time, ignores);
+        transform.translation += velocity.0.extend(0.) * delta;
+    }
+}
@@ -0,0 +1,373 @@
+use crate::abilities::Cooldown;
+use crate::collide::{Collideable, Destroyed, Health, Invulnerable};
+use crate::config::GameConfig;
+use crate::enemy::Enemy;
+use crate::game::{GameState, Speed};
+use crate::game_abilities::{DashAbility, EquippedWeapon, ShootAbility};
+use crate::player::Player;
+use crate::rng::{GameRng, RngStream};
+use crate::timestep::{FixedTime, FixedUpdateStage, TimeScale};
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use rand::Rng;
+
+const DROP_CHANCE: f64 = 0.15;
+const PICKUP_RADIUS: f32 = 10.;
+const PICKUP_SECONDS: f32 = 10.;
+
+const RAPID_FIRE_COOLDOWN: f32 = 0.4; // NOTE: of the usual shoot cooldown
+const SPEED_BOOST: f32 = 1.5;
+/// Angle between the bullets of a spread shot
+pub const SPREAD_SHOT_ANGLE: f32 = 0.25;
+
+/// Lets destroyed enemies drop pickups, and keeps track of the buffs the
+/// player got from them.
+pub struct PickupPlugin;
+
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_buff_hud))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(update_buff_hud))
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new()
+                    .with_system(
+                        tick_buffs
+                            .label("buffs")
+                            .after("input")
+                            .after("invulnerability")
+                            .before("movement")
+                            .before("action"),
+                    )
+                    .with_system(drop_pickups.after("collision").after("bomb"))
+                    .with_system(collect_pickups.after("collision")),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickupKind {
+    /// Gives back one hit
+    Heal,
+    /// Shortens the shoot cooldown for a while
+    RapidFire,
+    /// Every shot fires three bullets for a while
+    SpreadShot,
+    /// Invulnerable for a while
+    Shield,
+    /// Faster for a while
+    SpeedBoost,
+    /// The dash is ready again right away
+    DashRefill,
+}
+
+impl PickupKind {
+    pub const ALL: [PickupKind; 6] = [
+        PickupKind::Heal,
+        PickupKind::RapidFire,
+        PickupKind::SpreadShot,
+        PickupKind::Shield,
+        PickupKind::SpeedBoost,
+        PickupKind::DashRefill,
+    ];
+
+    /// How long the buff lasts, `None` for the ones that act right away.
+    pub fn seconds(self) -> Option<f32> {
+        match self {
+            PickupKind::Heal | PickupKind::DashRefill => None,
+            PickupKind::RapidFire | PickupKind::SpreadShot => Some(8.),
+            PickupKind::Shield => Some(5.),
+            PickupKind::SpeedBoost => Some(6.),
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            PickupKind::Heal => Color::LIME_GREEN,
+            PickupKind::RapidFire => Color::ORANGE,
+            PickupKind::SpreadShot => Color::GOLD,
+            PickupKind::Shield => Color::CYAN,
+            PickupKind::SpeedBoost => Color::AQUAMARINE,
+            PickupKind::DashRefill => Color::PINK,
+        }
+    }
+}
+
+/// Lies around until the player touches it, or its timer runs out.
+#[derive(Component)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    timer: Timer,
+}
+
+impl Pickup {
+    pub fn new(kind: PickupKind) -> Self {
+        Self {
+            kind,
+            timer: Timer::from_seconds(PICKUP_SECONDS, false),
+        }
+    }
+}
+
+pub struct Buff {
+    pub kind: PickupKind,
+    timer: Timer,
+}
+
+impl Buff {
+    pub fn percent_left(&self) -> f32 {
+        self.timer.percent_left()
+    }
+}
+
+/// Timed effects of the pickups the player collected.
+#[derive(Component, Default)]
+pub struct ActiveBuffs(pub Vec<Buff>);
+
+impl ActiveBuffs {
+    pub fn has(&self, kind: PickupKind) -> bool {
+        self.0.iter().any(|buff| buff.kind == kind)
+    }
+
+    /// Starts the buff, or starts it over if it is already active.
+    fn add(&mut self, kind: PickupKind, seconds: f32) {
+        self.0.retain(|buff| buff.kind != kind);
+        self.0.push(Buff {
+            kind,
+            timer: Timer::from_seconds(seconds, false),
+        });
+    }
+}
+
+#[derive(Component)]
+struct BuffBar(PickupKind);
+
+#[derive(Component)]
+struct BuffBarFill(PickupKind);
+
+fn drop_pickups(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    mut events: EventReader<Destroyed>,
+    q_enemy: Query<(), With<Enemy>>,
+) {
+    for event in events.iter() {
+        if q_enemy.get(event.entity).is_err() {
+            continue;
+        }
+        let rng = rng.stream(RngStream::Pickups);
+        if !rng.gen_bool(DROP_CHANCE) {
+            continue;
+        }
+        let kind = PickupKind::ALL[rng.gen_range(0..PickupKind::ALL.len())];
+        spawn_pickup(&mut commands, kind, event.position);
+    }
+}
+
+pub fn spawn_pickup(commands: &mut Commands, kind: PickupKind, position: Vec2) -> Entity {
+    let shape = shapes::RegularPolygon {
+        sides: 4,
+        feature: shapes::RegularPolygonFeature::Radius(PICKUP_RADIUS),
+        ..shapes::RegularPolygon::default()
+    };
+    commands
+        .spawn_bundle(GeometryBuilder::build_as(
+            &shape,
+            DrawMode::Outlined {
+                fill_mode: FillMode::color(kind.color()),
+                outline_mode: StrokeMode::new(Color::WHITE, 2.0),
+            },
+            Transform::from_translation(position.extend(5.)),
+        ))
+        .insert(Pickup::new(kind))
+        .id()
+}
+
+type CollectingPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static Collideable,
+        &'static mut Health,
+        &'static mut ActiveBuffs,
+        &'static Children,
+    ),
+    (With<Player>, Without<Pickup>),
+>;
+
+fn collect_pickups(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut q_pickups: Query<(Entity, &Transform, &mut Pickup)>,
+    mut q_player: CollectingPlayerQuery,
+    mut q_dash: Query<&mut Cooldown, With<DashAbility>>,
+) {
+    let mut player = q_player.get_single_mut().ok();
+    for (entity, transform, mut pickup) in q_pickups.iter_mut() {
+        if let Some((player_transform, collideable, health, buffs, children)) = player.as_mut() {
+            let distance = transform.translation.distance(player_transform.translation);
+            if distance < collideable.radius + PICKUP_RADIUS {
+                match pickup.kind {
+                    PickupKind::Heal => {
+                        health.current = (health.current + 1).min(health.max);
+                    }
+                    PickupKind::DashRefill => {
+                        for &child in children.iter() {
+                            if let Ok(mut cooldown) = q_dash.get_mut(child) {
+                                cooldown.finish();
+                            }
+                        }
+                    }
+                    kind => buffs.add(kind, kind.seconds().unwrap_or_default()),
+                }
+                commands.entity(entity).despawn();
+                continue;
+            }
+        }
+        if pickup.timer.tick(time_scale.delta(&time)).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+type BuffedPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut ActiveBuffs,
+        &'static mut Speed,
+        &'static Children,
+        Option<&'static Invulnerable>,
+    ),
+    With<Player>,
+>;
+
+type PlayerShootQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Cooldown,
+        &'static EquippedWeapon,
+        ChangeTrackers<EquippedWeapon>,
+    ),
+    With<ShootAbility>,
+>;
+
+/// Runs the buffs out, and applies what is left of them on top of the
+/// `GameConfig`.
+fn tick_buffs(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    config: Res<GameConfig>,
+    mut q_player: BuffedPlayerQuery,
+    mut q_shoot: PlayerShootQuery,
+) {
+    for (player, mut buffs, mut speed, children, invulnerable) in q_player.iter_mut() {
+        if !buffs.0.is_empty() {
+            for buff in buffs.0.iter_mut() {
+                buff.timer.tick(time.delta());
+            }
+            buffs.0.retain(|buff| !buff.timer.finished());
+        }
+        let refresh = buffs.is_changed() || config.is_changed();
+        for &child in children.iter() {
+            if let Ok((mut cooldown, weapon, weapon_tracker)) = q_shoot.get_mut(child) {
+                if !refresh && !weapon_tracker.is_changed() {
+                    continue;
+                }
+                let mut seconds = config.shoot_cooldown * weapon.0.stats().cooldown;
+                if buffs.has(PickupKind::RapidFire) {
+                    seconds *= RAPID_FIRE_COOLDOWN;
+                }
+                cooldown.set_duration(seconds);
+            }
+        }
+        if !refresh {
+            continue;
+        }
+
+        speed.0 = config.player_speed;
+        if buffs.has(PickupKind::SpeedBoost) {
+            speed.0 *= SPEED_BOOST;
+        }
+        let shield = buffs.0.iter().find(|buff| buff.kind == PickupKind::Shield);
+        if let Some(shield) = shield {
+            let left = shield.timer.duration().as_secs_f32() - shield.timer.elapsed_secs();
+            Invulnerable::extend_to(&mut commands, player, invulnerable, left);
+        }
+    }
+}
+
+fn spawn_buff_hud(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for kind in PickupKind::ALL {
+                if kind.seconds().is_none() {
+                    continue;
+                }
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            display: Display::None,
+                            size: Size::new(Val::Px(120.0), Val::Px(10.0)),
+                            margin: Rect::all(Val::Px(2.0)),
+                            padding: Rect::all(Val::Px(2.0)),
+                            ..Default::default()
+                        },
+                        color: Color::rgb(0.15, 0.15, 0.15).into(),
+                        ..Default::default()
+                    })
+                    .insert(BuffBar(kind))
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                                    ..Default::default()
+                                },
+                                color: kind.color().into(),
+                                ..Default::default()
+                            })
+                            .insert(BuffBarFill(kind));
+                    });
+            }
+        });
+}
+
+/// One bar per active buff, in the pickup's color, shrinking as it runs out.
+fn update_buff_hud(
+    q_buffs: Query<&ActiveBuffs, With<Player>>,
+    mut q_bars: Query<(&BuffBar, &mut Style), Without<BuffBarFill>>,
+    mut q_fills: Query<(&BuffBarFill, &mut Style), Without<BuffBar>>,
+) {
+    let buffs = q_buffs.get_single().ok();
+    let buff = |kind| buffs.and_then(|buffs| buffs.0.iter().find(|buff| buff.kind == kind));
+    for (bar, mut style) in q_bars.iter_mut() {
+        style.display = match buff(bar.0) {
+            Some(_) => Display::Flex,
+            None => Display::None,
+        };
+    }
+    for (fill, mut style) in q_fills.iter_mut() {
+        if let Some(buff) = buff(fill.0) {
+            style.size.width = Val::Percent(100. * buff.percent_left());
+        }
+    }
+}
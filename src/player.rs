@@ -1,10 +1,19 @@
-use crate::abilities::{Ability, Cooldown};
+use crate::abilities::{find_ability, Ability, Cooldown};
 use crate::actions::*;
-use crate::bullet::create_bullet_bundle;
-use crate::collide::{Collideable, Collider, DetectLeave};
-use crate::game::{GameState, Speed, BASE_RADIUS, BASE_SPEED, BULLET_SPEED};
+use crate::arena::{Arena, Cursor};
+use crate::bullet::{heading, muzzle_transform, CancelsBullets, Pierce};
+use crate::bullet_pool::{BulletPool, BulletStyle};
+use crate::collide::{Beam, Collideable, Collider, Damage, DetectLeave, Health, Invulnerable};
+use crate::config::GameConfig;
+use crate::enemy::EnemyBullet;
+use crate::game::{GameState, Speed};
 use crate::game_abilities::*;
+use crate::loading::FontAssets;
+use crate::movement::*;
+use crate::pickup::{ActiveBuffs, PickupKind, SPREAD_SHOT_ANGLE};
+use crate::timestep::{FixedTime, FixedUpdateStage, IgnoresTimeScale};
 use crate::utils::*;
+use bevy::ecs::system::{EntityCommands, SystemParam};
 use bevy::prelude::*;
 use bevy_prototype_lyon::entity::ShapeBundle;
 use bevy_prototype_lyon::prelude::*;
@@ -12,20 +21,53 @@ use leafwing_input_manager::prelude::*;
 use std::f32::consts::PI;
 
 const PLAYER_BASE_ANGLE: f32 = -PI / 2.0;
+/// Size of the shield compared to the player
+const SHIELD_SCALE: f32 = 1.5;
+/// Score for every enemy bullet erased by a player bullet
+const CANCEL_BONUS: u32 = 10;
+/// How long a beam stays, shorter than the laser's cooldown
+const BEAM_SECONDS: f32 = 0.1;
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_player))
-            .add_system_set(
-                SystemSet::on_update(GameState::Playing)
-                    .with_system(cursor_system)
-                    .with_system(handle_movement_events.after("input").label("movement"))
-                    .with_system(handle_shoot_events.after("input").label("action"))
-                    .with_system(handle_dash_events.after("input").label("action"))
-                    .with_system(go_to_menu_again),
-            );
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing)
+                // NOTE: a replay brings its own config
+                .with_system(spawn_player.after("replay"))
+                .with_system(spawn_weapon_text),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(update_weapon_text)
+                .with_system(update_charge_indicator)
+                .with_system(update_shield_indicator),
+        )
+        .add_system_set_to_stage(
+            FixedUpdateStage,
+            SystemSet::new()
+                .with_system(cursor_system.before("input"))
+                .with_system(handle_movement_events.after("input").label("movement"))
+                .with_system(handle_shoot_events.after("input").label("action"))
+                .with_system(
+                    // NOTE: the buffs apply the new weapon's cooldown
+                    handle_switch_weapon_events.after("input").before("buffs"),
+                )
+                .with_system(handle_dash_events.after("movement").label("action"))
+                .with_system(update_dash.after("accelerate").before("velocity"))
+                .with_system(handle_shield_events.after("input").label("action"))
+                .with_system(
+                    // NOTE: after the bullets moved, before they hit anything
+                    update_shield
+                        .after("action")
+                        .after("velocity")
+                        .before("bullet_cancelling"),
+                )
+                .with_system(fade_afterimages)
+                .with_system(fade_beams)
+                .with_system(end_run),
+        );
     }
 }
 
@@ -44,10 +86,10 @@ pub struct PlayerBundle {
     pub input_manager: InputManagerBundle<Actions>,
 }
 
-fn spawn_player(mut commands: Commands, actions_map: Res<ActionsMap>) {
+fn spawn_player(mut commands: Commands, actions_map: Res<ActionsMap>, config: Res<GameConfig>) {
     let shape = shapes::RegularPolygon {
         sides: 3,
-        feature: shapes::RegularPolygonFeature::Radius(BASE_RADIUS),
+        feature: shapes::RegularPolygonFeature::Radius(config.player_radius),
         ..shapes::RegularPolygon::default()
     };
 
@@ -71,35 +113,116 @@ fn spawn_player(mut commands: Commands, actions_map: Res<ActionsMap>) {
             },
         })
         .insert(Collideable {
-            radius: BASE_RADIUS,
+            radius: config.player_radius,
         })
         .insert(DetectLeave)
-        .insert(Speed(BASE_SPEED))
+        .insert(Health::new(config.player_health))
+        .insert(ActiveBuffs::default())
+        .insert(ActionHolds::default())
+        .insert(Speed(config.player_speed))
+        .insert_bundle(MovementBundle::default())
+        .insert(Handling::PLAYER)
+        .insert(IgnoresTimeScale)
         .id();
 
     let shoot_ability = commands
         .spawn_bundle(ShootAbilityBundle {
             marker: ShootAbility,
-            cooldown: Cooldown::new(0.3),
+            cooldown: Cooldown::new(config.shoot_cooldown),
         })
+        .insert(EquippedWeapon(WeaponKind::Pistol))
+        .insert(ShotCharge::default())
         .insert(Ability)
+        .insert(IgnoresTimeScale)
         .id();
 
     let dash_ability = commands
         .spawn_bundle(DashAbilityBundle {
-            marker: DashAbility,
-            cooldown: Cooldown::new(10.),
+            marker: DashAbility {
+                distance: config.dash_distance,
+                duration: config.dash_duration,
+            },
+            cooldown: Cooldown::new(config.dash_cooldown),
         })
         .insert(Ability)
+        .insert(IgnoresTimeScale)
         .id();
 
-    commands
-        .entity(player)
-        .push_children(&[shoot_ability, dash_ability]);
+    let shield_ability = commands
+        .spawn_bundle(ShieldAbilityBundle {
+            marker: ShieldAbility {
+                parry_window: config.parry_window,
+                block_duration: config.block_duration,
+            },
+            cooldown: Cooldown::new(config.shield_cooldown),
+        })
+        .insert(Ability)
+        .insert(IgnoresTimeScale)
+        .id();
+
+    let bomb_ability = commands
+        .spawn_bundle(BombAbilityBundle {
+            marker: BombAbility {
+                radius: config.bomb_radius,
+                grace_time: config.bomb_grace_time,
+                damage: config.bomb_damage,
+            },
+            cooldown: Cooldown::new(config.bomb_cooldown),
+            stock: BombStock::new(config.bomb_stock, config.kills_per_bomb),
+        })
+        .insert(Ability)
+        .insert(IgnoresTimeScale)
+        .id();
+
+    let bullet_time_ability = commands
+        .spawn()
+        .insert(BulletTimeAbility {
+            time_scale: config.bullet_time_scale,
+            refill_rate: config.bullet_time_refill,
+        })
+        .insert(BulletTimeMeter::new(config.bullet_time_seconds))
+        .insert(Ability)
+        .insert(IgnoresTimeScale)
+        .id();
+
+    let charge_indicator = commands
+        .spawn_bundle(GeometryBuilder::build_as(
+            &shapes::Circle {
+                radius: config.player_radius,
+                center: Vec2::ZERO,
+            },
+            DrawMode::Stroke(StrokeMode::new(Color::FUCHSIA, 2.0)),
+            Transform::from_xyz(0., 0., 1.),
+        ))
+        .insert(ChargeIndicator)
+        .id();
+
+    let mut shield_indicator = GeometryBuilder::build_as(
+        &shapes::Circle {
+            radius: config.player_radius * SHIELD_SCALE,
+            center: Vec2::ZERO,
+        },
+        DrawMode::Stroke(StrokeMode::new(Color::CYAN, 3.0)),
+        Transform::from_xyz(0., 0., 1.),
+    );
+    shield_indicator.visibility.is_visible = false;
+    let shield_indicator = commands
+        .spawn_bundle(shield_indicator)
+        .insert(ShieldIndicator)
+        .id();
+
+    commands.entity(player).push_children(&[
+        shoot_ability,
+        dash_ability,
+        shield_ability,
+        bomb_ability,
+        bullet_time_ability,
+        charge_indicator,
+        shield_indicator,
+    ]);
 }
 
-fn cursor_system(windows: Res<Windows>, mut q_player: Query<&mut Transform, With<Player>>) {
-    let window = windows.get_primary().unwrap();
+fn cursor_system(cursor: Res<Cursor>, mut q_player: Query<&mut Transform, With<Player>>) {
     let player_transform = q_player.get_single_mut();
     if let Err(err) = player_transform {
         eprintln!("{:?}", err);
@@ -107,94 +230,544 @@ fn cursor_system(windows: Res<Windows>, mut q_player: Query<&mut Transform, With
     }
     let mut player_transform = player_transform.unwrap();
 
-    if let Some(angle) = get_angle_between_transform_and_cursor(window, &player_transform) {
+    if let Some(angle) = get_angle_between_transform_and_cursor(&cursor, &player_transform) {
         player_transform.rotation = Quat::from_rotation_z(angle + PLAYER_BASE_ANGLE);
     }
 }
 
 fn handle_movement_events(
     mut events: EventReader<MovementEvent>,
-    mut q_player: Query<(&mut Transform, &Speed), With<Player>>,
-    time: Res<Time>,
+    mut q_player: Query<&mut Thrust, With<Player>>,
 ) {
-    let player = q_player.get_single_mut();
-    if let Err(err) = player {
+    let thrust = q_player.get_single_mut();
+    if let Err(err) = thrust {
         eprintln!("{:?}", err);
         return;
     }
-    let (mut player_transform, speed) = player.unwrap();
+    let mut thrust = thrust.unwrap();
 
+    // NOTE: no event this frame means no key is held, so the player slows down
+    thrust.0 = Vec2::ZERO;
     for event in events.iter() {
-        player_transform.translation +=
-            Vec3::from(event.direction) * time.delta_seconds() * speed.0;
+        thrust.0 = Vec3::from(event.direction).truncate();
+    }
+}
+
+type ChargingShootQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Cooldown,
+        &'static EquippedWeapon,
+        &'static mut ShotCharge,
+    ),
+    With<ShootAbility>,
+>;
+
+/// What the player fires its weapons with.
+#[derive(SystemParam)]
+struct PlayerGuns<'w, 's> {
+    commands: Commands<'w, 's>,
+    pool: ResMut<'w, BulletPool>,
+    config: Res<'w, GameConfig>,
+    arena: Res<'w, Arena>,
+}
+
+impl<'w, 's> PlayerGuns<'w, 's> {
+    /// Fires one shot of `weapon` towards `aim` from in front of the `shooter`,
+    /// once for every `spread` offset.
+    fn fire(&mut self, shooter: &Transform, weapon: WeaponStats, spread: &[f32], aim: f32) {
+        let mut bullet_transform = *shooter;
+        bullet_transform.translation.z -= 1.;
+        let style = BulletStyle {
+            radius: self.config.bullet_radius * weapon.bullet_radius,
+            color: weapon.color,
+        };
+        for offset in spread {
+            for angle in weapon.angles(aim + offset) {
+                let transform =
+                    muzzle_transform(bullet_transform, angle, self.config.muzzle_offset);
+                let mut bullet = if weapon.beam {
+                    self.spawn_beam(transform, angle, style)
+                } else {
+                    self.pool.spawn(
+                        &mut self.commands,
+                        transform,
+                        angle,
+                        self.config.bullet_speed * weapon.bullet_speed,
+                        style,
+                    )
+                };
+                bullet
+                    .insert(Collider {
+                        radius: style.radius,
+                    })
+                    .insert(PlayerBullet);
+                if weapon.pierce > 0 {
+                    bullet.insert(Pierce::new(weapon.pierce));
+                }
+                if weapon.damage > 1 {
+                    bullet.insert(Damage(weapon.damage));
+                }
+                if weapon.cancels_bullets {
+                    bullet.insert(CancelsBullets {
+                        remaining: None,
+                        bonus: CANCEL_BONUS,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Spawns a beam towards `angle` that reaches across the whole arena.
+    fn spawn_beam(
+        &mut self,
+        transform: Transform,
+        angle: f32,
+        style: BulletStyle,
+    ) -> EntityCommands<'w, 's, '_> {
+        let reach = heading(angle) * 2. * self.arena.half_extents().length();
+        let mut beam = self.commands.spawn_bundle(GeometryBuilder::build_as(
+            &shapes::Line(Vec2::ZERO, reach),
+            DrawMode::Stroke(StrokeMode::new(style.color, 2. * style.radius)),
+            transform,
+        ));
+        beam.insert(Beam { reach }).insert(BeamFade {
+            timer: Timer::from_seconds(BEAM_SECONDS, false),
+        });
+        beam
     }
 }
 
 fn handle_shoot_events(
-    mut commands: Commands,
+    mut guns: PlayerGuns,
+    time: Res<FixedTime>,
     mut events: EventReader<ShootEvent>,
-    q_player: Query<(&Transform, &Children), With<Player>>,
-    mut q_ability: Query<&mut Cooldown, With<ShootAbility>>,
+    q_player: Query<(&Transform, &Children, &ActiveBuffs), With<Player>>,
+    mut q_ability: ChargingShootQuery,
 ) {
     let player_transform = q_player.get_single();
     if let Err(err) = player_transform {
         eprintln!("{:?}", err);
         return;
     }
-    let (player_transform, children) = player_transform.unwrap();
-
-    let mut bullet_transform = player_transform.clone();
-    bullet_transform.translation.z -= 1.;
+    let (player_transform, children, buffs) = player_transform.unwrap();
+    let spread: &[f32] = if buffs.has(PickupKind::SpreadShot) {
+        &[-SPREAD_SHOT_ANGLE, 0., SPREAD_SHOT_ANGLE]
+    } else {
+        &[0.]
+    };
 
+    let ability =
+        find_ability(children, &q_ability).and_then(|ability| q_ability.get_mut(ability).ok());
+    let (mut cd, weapon, mut charge) = match ability {
+        Some(ability) => ability,
+        None => return,
+    };
+    let weapon = weapon.0.stats();
     for event in events.iter() {
-        let mut cd = q_ability.get_mut(children[0]).unwrap();
-        if !cd.finished() {
-            return;
+        // NOTE: charging weapons fire when let go, the others while held
+        if weapon.charge_time.is_none() {
+            if !event.released && cd.finished() {
+                guns.fire(player_transform, weapon, spread, event.angle);
+                cd.start();
+            }
+        } else if event.released {
+            charge.released = Some(event.angle);
+        } else if cd.finished() && event.held > 0. {
+            charge.seconds += time.delta_seconds();
+        } else {
+            charge.seconds = 0.;
         }
-        commands
-            .spawn_bundle(create_bullet_bundle(
-                bullet_transform,
-                event.angle,
-                BULLET_SPEED,
-                Color::ORANGE,
-            ))
-            .insert(Collider { radius: 8.0 })
-            .insert(PlayerBullet);
+    }
+    if let Some(aim) = charge.released.filter(|_| cd.finished()) {
+        guns.fire(
+            player_transform,
+            weapon.charged(charge.seconds),
+            spread,
+            aim,
+        );
         cd.start();
+        *charge = ShotCharge::default();
     }
 }
 
+/// Ring around the player that grows while a shot charges.
+#[derive(Component)]
+struct ChargeIndicator;
+
+fn update_charge_indicator(
+    q_player: Query<(&ActionHolds, &Children), With<Player>>,
+    q_ability: Query<(&Cooldown, &EquippedWeapon, &ShotCharge)>,
+    mut q_indicator: Query<(&mut Transform, &mut Visibility), With<ChargeIndicator>>,
+) {
+    for (holds, children) in q_player.iter() {
+        let ability = children.iter().find_map(|&child| q_ability.get(child).ok());
+        let (cooldown, weapon, charge) = match ability {
+            Some(ability) => ability,
+            None => continue,
+        };
+        let stats = weapon.0.stats();
+        let charging = stats.charge_time.is_some()
+            && cooldown.finished()
+            && holds.held(Actions::Shoot).is_some();
+        for &child in children.iter() {
+            if let Ok((mut transform, mut visibility)) = q_indicator.get_mut(child) {
+                visibility.is_visible = charging;
+                if charging {
+                    transform.scale = Vec3::splat(1. + stats.charge(charge.seconds));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct WeaponText;
+
+/// NOTE: the headless game has no fonts, and goes without
+fn spawn_weapon_text(mut commands: Commands, font_assets: Option<Res<FontAssets>>) {
+    let font_assets = match font_assets {
+        Some(font_assets) => font_assets,
+        None => return,
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 24.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(WeaponText);
+}
+
+fn update_weapon_text(
+    q_weapon: Query<&EquippedWeapon, Changed<EquippedWeapon>>,
+    mut q_text: Query<&mut Text, With<WeaponText>>,
+) {
+    for weapon in q_weapon.iter() {
+        for mut text in q_text.iter_mut() {
+            text.sections[0].value = weapon.0.name().to_string();
+        }
+    }
+}
+
+fn handle_switch_weapon_events(
+    mut events: EventReader<SwitchWeaponEvent>,
+    q_player: Query<&Children, With<Player>>,
+    mut q_weapon: Query<&mut EquippedWeapon>,
+) {
+    let children = match q_player.get_single() {
+        Ok(children) => children,
+        Err(_) => return,
+    };
+    let weapon = find_ability(children, &q_weapon).and_then(|weapon| q_weapon.get_mut(weapon).ok());
+    let mut weapon = match weapon {
+        Some(weapon) => weapon,
+        None => return,
+    };
+    for event in events.iter() {
+        weapon.0 = weapon.0.cycled(event.offset);
+    }
+}
+
+type DashReadyPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static Thrust,
+        &'static Children,
+        Option<&'static Invulnerable>,
+    ),
+    (With<Player>, Without<Dashing>),
+>;
+
 fn handle_dash_events(
-    windows: Res<Windows>,
+    mut commands: Commands,
+    cursor: Res<Cursor>,
+    config: Res<GameConfig>,
     mut events: EventReader<DashEvent>,
-    mut q_player: Query<(&mut Transform, &Children), With<Player>>,
-    mut q_ability: Query<&mut Cooldown, With<DashAbility>>,
+    q_player: DashReadyPlayerQuery,
+    mut q_ability: Query<(&DashAbility, &mut Cooldown)>,
 ) {
-    let window = windows.get_primary().unwrap();
-    let player_transform = q_player.get_single_mut();
-    if let Err(err) = player_transform {
-        eprintln!("{:?}", err);
+    // NOTE: presses made during a dash are used up too, not kept for when it ends
+    if events.iter().count() == 0 {
+        return;
+    }
+    let (player, player_transform, thrust, children, invulnerable) = match q_player.get_single() {
+        Ok(player) => player,
+        // NOTE: already dashing
+        Err(_) => return,
+    };
+
+    let ability =
+        find_ability(children, &q_ability).and_then(|ability| q_ability.get_mut(ability).ok());
+    let (dash, mut cd) = match ability {
+        Some(ability) => ability,
+        None => return,
+    };
+    if !cd.finished() {
         return;
     }
-    let (mut player_transform, children) = player_transform.unwrap();
+    // Dash where we are going, or where we are aiming when standing still
+    let direction = if thrust.0 != Vec2::ZERO {
+        thrust.0.normalize()
+    } else if let Some(angle) = get_angle_between_transform_and_cursor(&cursor, player_transform) {
+        Vec2::new(angle.cos(), angle.sin())
+    } else {
+        return;
+    };
+
+    commands
+        .entity(player)
+        .insert(Dashing::new(direction, dash));
+    Invulnerable::extend_to(
+        &mut commands,
+        player,
+        invulnerable,
+        dash.duration + config.dash_grace_time,
+    );
+    cd.start();
+}
 
-    for _ in events.iter() {
-        let mut cd = q_ability.get_mut(children[1]).unwrap();
-        if !cd.finished() {
-            return;
+type ShieldReadyPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static Children, Option<&'static Invulnerable>),
+    (With<Player>, Without<Shielding>),
+>;
+
+fn handle_shield_events(
+    mut commands: Commands,
+    mut events: EventReader<ShieldEvent>,
+    q_player: ShieldReadyPlayerQuery,
+    mut q_ability: Query<(&ShieldAbility, &mut Cooldown)>,
+) {
+    let (player, children, invulnerable) = match q_player.get_single() {
+        Ok(player) => player,
+        // NOTE: the shield is already up
+        Err(_) => return,
+    };
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    let ability =
+        find_ability(children, &q_ability).and_then(|ability| q_ability.get_mut(ability).ok());
+    let (shield, mut cd) = match ability {
+        Some(ability) => ability,
+        None => return,
+    };
+    if !cd.finished() {
+        return;
+    }
+    commands.entity(player).insert(Shielding::new(shield));
+    // NOTE: the block also keeps enemies that ram the player from hurting
+    Invulnerable::extend_to(&mut commands, player, invulnerable, shield.block_duration);
+    cd.start();
+}
+
+/// Reflects the enemy bullets that touch the shield while parrying, and
+/// absorbs them for the rest of the block.
+fn update_shield(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut pool: ResMut<BulletPool>,
+    mut q_player: Query<(Entity, &Transform, &Collideable, &mut Shielding), With<Player>>,
+    q_enemy_bullets: Query<(Entity, &Transform, &Collider, &Velocity), With<EnemyBullet>>,
+) {
+    let (player, player_transform, collideable, mut shielding) = match q_player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let shield_radius = collideable.radius * SHIELD_SCALE;
+    let parrying = shielding.is_parrying();
+    for (bullet, transform, collider, velocity) in q_enemy_bullets.iter() {
+        if pool.is_released(bullet) {
+            continue;
+        }
+        let distance = transform.translation.distance(player_transform.translation);
+        if distance >= shield_radius + collider.radius {
+            continue;
         }
-        if let Some(angle) = get_angle_between_transform_and_cursor(window, &player_transform) {
-            let magnitude: f32 = 150.;
-            player_transform.translation.x += angle.cos() * magnitude;
-            player_transform.translation.y += angle.sin() * magnitude;
+        pool.release(&mut commands, bullet);
+        if !parrying {
+            continue;
+        }
+        let angle = velocity.0.y.atan2(velocity.0.x) + PI;
+        let style = BulletStyle {
+            radius: collider.radius,
+            color: Color::CYAN,
+        };
+        let mut bullet_transform = *player_transform;
+        bullet_transform.translation = transform.translation;
+        pool.spawn(
+            &mut commands,
+            bullet_transform,
+            angle,
+            velocity.0.length(),
+            style,
+        )
+        .insert(Collider {
+            radius: style.radius,
+        })
+        .insert(PlayerBullet);
+    }
 
-            cd.start();
+    if shielding.timer.tick(time.delta()).finished() {
+        commands.entity(player).remove::<Shielding>();
+    }
+}
+
+/// Ring around the player while the shield is up, brighter while parrying.
+#[derive(Component)]
+struct ShieldIndicator;
+
+fn update_shield_indicator(
+    q_player: Query<(Option<&Shielding>, &Children), With<Player>>,
+    mut q_indicator: Query<(&mut DrawMode, &mut Visibility), With<ShieldIndicator>>,
+) {
+    for (shielding, children) in q_player.iter() {
+        for &child in children.iter() {
+            if let Ok((mut draw_mode, mut visibility)) = q_indicator.get_mut(child) {
+                visibility.is_visible = shielding.is_some();
+                let color = match shielding {
+                    Some(shielding) if shielding.is_parrying() => Color::WHITE,
+                    _ => Color::CYAN,
+                };
+                *draw_mode = DrawMode::Stroke(StrokeMode::new(color, 3.0));
+            }
+        }
+    }
+}
+
+type DashingPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static Collideable,
+        &'static mut Velocity,
+        &'static mut Dashing,
+        &'static Speed,
+    ),
+    With<Player>,
+>;
+
+fn update_dash(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    arena: Res<Arena>,
+    mut q_player: DashingPlayerQuery,
+) {
+    let half_extents = arena.half_extents();
+    let delta = time.delta_seconds();
+
+    for (player, transform, collideable, mut velocity, mut dashing, speed) in q_player.iter_mut() {
+        if dashing.trail_timer.tick(time.delta()).just_finished() {
+            spawn_afterimage(&mut commands, transform, collideable.radius);
+        }
+        if dashing.timer.tick(time.delta()).finished() {
+            velocity.0 = dashing.direction * speed.0;
+            commands.entity(player).remove::<Dashing>();
+            continue;
+        }
+
+        velocity.0 = dashing.direction * dashing.speed;
+        // Stop at the edge of the arena instead of dashing through it
+        let next = transform.translation.truncate() + velocity.0 * delta;
+        if next.x.abs() > half_extents.x || next.y.abs() > half_extents.y {
+            let clamped = next.clamp(-half_extents, half_extents);
+            if delta > 0. {
+                velocity.0 = (clamped - transform.translation.truncate()) / delta;
+            }
+            commands.entity(player).remove::<Dashing>();
+        }
+    }
+}
+
+#[derive(Component)]
+struct Afterimage {
+    timer: Timer,
+}
+
+fn spawn_afterimage(commands: &mut Commands, player_transform: &Transform, radius: f32) {
+    let shape = shapes::RegularPolygon {
+        sides: 3,
+        feature: shapes::RegularPolygonFeature::Radius(radius),
+        ..shapes::RegularPolygon::default()
+    };
+    let mut transform = *player_transform;
+    transform.translation.z -= 2.;
+
+    commands
+        .spawn_bundle(GeometryBuilder::build_as(
+            &shape,
+            DrawMode::Fill(FillMode::color(Color::rgba(0.5, 0., 0.5, 0.4))),
+            transform,
+        ))
+        .insert(Afterimage {
+            timer: Timer::from_seconds(0.25, false),
+        });
+}
+
+fn fade_afterimages(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut query: Query<(Entity, &mut Transform, &mut Afterimage)>,
+) {
+    for (entity, mut transform, mut afterimage) in query.iter_mut() {
+        if afterimage.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.scale = Vec3::splat(afterimage.timer.percent_left());
+    }
+}
+
+/// A beam only hurts for a moment after being fired.
+#[derive(Component)]
+struct BeamFade {
+    timer: Timer,
+}
+
+fn fade_beams(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut query: Query<(Entity, &mut DrawMode, &mut BeamFade)>,
+) {
+    for (entity, mut draw_mode, mut fade) in query.iter_mut() {
+        if fade.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if let DrawMode::Stroke(stroke) = draw_mode.as_mut() {
+            stroke.color.set_a(fade.timer.percent_left());
         }
     }
 }
 
-fn go_to_menu_again(mut state: ResMut<State<GameState>>, q_player: Query<&Player>) {
+fn end_run(mut state: ResMut<State<GameState>>, q_player: Query<&Player>) {
     if q_player.is_empty() {
-        state.set(GameState::Menu).unwrap();
+        // NOTE: several steps of the same frame may get here before the state changes
+        state.overwrite_set(GameState::GameOver).unwrap();
     }
 }
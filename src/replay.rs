@@ -0,0 +1,282 @@
+use crate::actions::{Actions, WeaponScroll};
+use crate::arena::{Arena, Cursor};
+use crate::config::GameConfig;
+use crate::difficulty::DifficultySettings;
+use crate::enemy_archetype::{EnemyArchetypes, EnemyKind};
+use crate::game::GameState;
+use crate::player::Player;
+use crate::rng::GameRng;
+use crate::timestep::{FixedTime, FixedUpdateStage};
+use crate::waves::WaveScript;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+const REPLAY_VERSION: u32 = 7;
+
+/// Records the player's input every fixed step, or feeds a recorded run back
+/// in place of the live input, depending on the `ReplayMode`.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayMode>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(start_replay.label("replay").after("rng")),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(save_recording))
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new().with_system(record_or_play_back_input.before("input")),
+            );
+    }
+}
+
+#[derive(Default)]
+pub enum ReplayMode {
+    #[default]
+    Live,
+    /// Every run is written to `path` once it is over, replacing the last one.
+    Recording { path: PathBuf, replay: Replay },
+    /// The live input is ignored in favour of the recorded one.
+    Playback {
+        replay: Replay,
+        next: usize,
+        input: TickInput,
+    },
+}
+
+impl ReplayMode {
+    /// `--record <file>` records every run, `--replay <file>` plays one back.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> io::Result<Self> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let path = match arg.as_str() {
+                "--record" | "--replay" => args.next().map(PathBuf::from).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("{} needs a file", arg))
+                })?,
+                _ => continue,
+            };
+            return Ok(if arg == "--record" {
+                ReplayMode::Recording {
+                    path,
+                    replay: Replay::default(),
+                }
+            } else {
+                ReplayMode::playback(Replay::load(path)?)
+            });
+        }
+        Ok(ReplayMode::Live)
+    }
+
+    pub fn playback(replay: Replay) -> Self {
+        ReplayMode::Playback {
+            replay,
+            next: 0,
+            input: TickInput::default(),
+        }
+    }
+}
+
+/// Everything needed to play a run again: the fixed steps make the rest of
+/// the game deterministic.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub arena: [f32; 2], // NOTE: at the start of the run
+    pub difficulty: DifficultySettings,
+    pub config: GameConfig,
+    /// See `data_hash`, the data files are too big to keep in every replay
+    pub data_hash: u64,
+    /// NOTE: only the ticks where the input changed, in order
+    pub inputs: Vec<(u64, TickInput)>,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let replay: Replay = bincode::deserialize_from(BufReader::new(File::open(path)?))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported replay version {}", replay.version),
+            ));
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        bincode::serialize_into(BufWriter::new(File::create(path)?), self).map_err(io::Error::other)
+    }
+}
+
+/// The player's input during one fixed step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickInput {
+    /// One bit per `Actions` variant that is held, in declaration order.
+    pub actions: u16,
+    pub cursor: Option<[f32; 2]>,
+    /// Mouse wheel notches, only on the step they were used on
+    pub scroll: i16,
+}
+
+impl TickInput {
+    fn read(action_state: &ActionState<Actions>, cursor: &Cursor, scroll: &WeaponScroll) -> Self {
+        let actions = Actions::iter()
+            .enumerate()
+            .filter(|(_, action)| action_state.pressed(action))
+            .fold(0, |bits, (i, _)| bits | 1 << i);
+        Self {
+            actions,
+            cursor: cursor.position.map(|position| position.to_array()),
+            scroll: scroll.0,
+        }
+    }
+
+    fn apply(
+        &self,
+        action_state: &mut ActionState<Actions>,
+        cursor: &mut Cursor,
+        scroll: &mut WeaponScroll,
+    ) {
+        for (i, action) in Actions::iter().enumerate() {
+            if self.actions & 1 << i != 0 {
+                action_state.press(&action);
+            } else {
+                action_state.release(&action);
+            }
+        }
+        cursor.position = self.cursor.map(Vec2::from);
+        scroll.0 = self.scroll;
+    }
+}
+
+/// A fingerprint of the enemy archetypes and the waves, the same on every
+/// machine for the same data.
+pub fn data_hash(archetypes: &EnemyArchetypes, script: &WaveScript) -> u64 {
+    // NOTE: FNV-1a, std's hashers are not guaranteed to stay the same
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |text: String| {
+        for byte in text.bytes() {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    // NOTE: in a fixed order, the archetypes are kept in a `HashMap`
+    for kind in EnemyKind::ALL {
+        write(format!("{:?}", archetypes.get(kind)));
+    }
+    write(format!("{:?}", script.waves));
+    hash
+}
+
+/// What a run is played with, a replay brings its own.
+#[derive(SystemParam)]
+struct RunSettings<'w, 's> {
+    rng: ResMut<'w, GameRng>,
+    arena: ResMut<'w, Arena>,
+    difficulty: ResMut<'w, DifficultySettings>,
+    config: ResMut<'w, GameConfig>,
+    archetypes: Res<'w, EnemyArchetypes>,
+    script: Res<'w, WaveScript>,
+    #[system_param(ignore)]
+    _marker: std::marker::PhantomData<&'s ()>,
+}
+
+fn start_replay(
+    mut mode: ResMut<ReplayMode>,
+    mut state: ResMut<State<GameState>>,
+    mut run: RunSettings,
+) {
+    let data_hash = data_hash(&run.archetypes, &run.script);
+    match &mut *mode {
+        ReplayMode::Live => {}
+        ReplayMode::Recording { replay, .. } => {
+            *replay = Replay {
+                version: REPLAY_VERSION,
+                seed: run.rng.seed().unwrap_or_default(),
+                arena: [run.arena.width, run.arena.height],
+                difficulty: *run.difficulty,
+                config: run.config.clone(),
+                data_hash,
+                inputs: Vec::new(),
+            };
+        }
+        ReplayMode::Playback { replay, .. } if replay.data_hash != data_hash => {
+            eprintln!("The replay was recorded with other enemy or wave data, not playing it");
+            *mode = ReplayMode::Live;
+            let _ = state.overwrite_set(GameState::Menu);
+        }
+        ReplayMode::Playback {
+            replay,
+            next,
+            input,
+        } => {
+            *next = 0;
+            *input = TickInput::default();
+            run.rng.reseed(replay.seed);
+            run.arena.width = replay.arena[0];
+            run.arena.height = replay.arena[1];
+            *run.difficulty = replay.difficulty;
+            *run.config = replay.config.clone();
+        }
+    }
+}
+
+fn record_or_play_back_input(
+    mut mode: ResMut<ReplayMode>,
+    fixed_time: Res<FixedTime>,
+    mut arena: ResMut<Arena>,
+    mut cursor: ResMut<Cursor>,
+    mut scroll: ResMut<WeaponScroll>,
+    mut q_player: Query<&mut ActionState<Actions>, With<Player>>,
+) {
+    let mut action_state = match q_player.get_single_mut() {
+        Ok(action_state) => action_state,
+        Err(_) => return,
+    };
+    let tick = fixed_time.ticks();
+
+    match &mut *mode {
+        ReplayMode::Live => {}
+        ReplayMode::Recording { replay, .. } => {
+            let input = TickInput::read(&action_state, &cursor, &scroll);
+            if replay.inputs.last().map(|(_, last)| *last) != Some(input) {
+                replay.inputs.push((tick, input));
+            }
+        }
+        ReplayMode::Playback {
+            replay,
+            next,
+            input,
+        } => {
+            while let Some((_, recorded)) = replay
+                .inputs
+                .get(*next)
+                .filter(|(recorded_tick, _)| *recorded_tick <= tick)
+            {
+                *input = *recorded;
+                *next += 1;
+            }
+            input.apply(&mut action_state, &mut cursor, &mut scroll);
+            // NOTE: the notches are used up, unlike the held actions
+            input.scroll = 0;
+            // NOTE: the window keeps resizing the arena, the recorded run did not
+            arena.width = replay.arena[0];
+            arena.height = replay.arena[1];
+        }
+    }
+}
+
+fn save_recording(mode: Res<ReplayMode>) {
+    if let ReplayMode::Recording { path, replay } = &*mode {
+        if let Err(err) = replay.save(path) {
+            eprintln!("Failed to save the replay to {:?}: {}", path, err);
+        }
+    }
+}
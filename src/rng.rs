@@ -0,0 +1,58 @@
+use crate::game::GameState;
+use bevy::prelude::*;
+use rand_pcg::Pcg32;
+use std::collections::HashMap;
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRng>().add_system_set(
+            SystemSet::on_enter(GameState::Playing).with_system(seed_game_rng.label("rng")),
+        );
+    }
+}
+
+/// Independent random number streams, so a new random call in one system does
+/// not change what another one rolls.
+///
+/// NOTE: only ever append variants, the position picks the stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RngStream {
+    EnemyAim,
+    Pickups,
+}
+
+/// All randomness of a run comes from here, reseeded every time a run starts
+/// so the run can be reproduced from its seed.
+#[derive(Default)]
+pub struct GameRng {
+    /// Seed every run uses instead of a random one, e.g. for tests.
+    pub fixed_seed: Option<u64>,
+    seed: Option<u64>,
+    streams: HashMap<RngStream, Pcg32>,
+}
+
+impl GameRng {
+    /// Seed of the current (or last) run, `None` before the first one.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.streams.clear();
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut Pcg32 {
+        let seed = self.seed.unwrap_or_default();
+        self.streams
+            .entry(stream)
+            .or_insert_with(|| Pcg32::new(seed, stream as u64))
+    }
+}
+
+fn seed_game_rng(mut rng: ResMut<GameRng>) {
+    let seed = rng.fixed_seed.unwrap_or_else(rand::random);
+    rng.reseed(seed);
+}
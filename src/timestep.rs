@@ -0,0 +1,253 @@
+use crate::game::GameState;
+use crate::movement::Velocity;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use std::time::Duration;
+
+/// Runs the gameplay in fixed steps, so a run plays out the same on every
+/// machine and at every frame rate given the same inputs.
+///
+/// Gameplay systems go into `FixedUpdateStage` and read `FixedTime` instead of
+/// `Time`. The stage runs as many steps per frame as real time asks for, and
+/// moving entities are drawn interpolated between their last two steps.
+///
+/// NOTE: bevy picks a different order for unordered systems on every run, so
+/// systems in the stage that touch the same data need explicit labels to
+/// stay reproducible.
+pub struct FixedTimestepPlugin;
+
+impl Plugin for FixedTimestepPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FixedTime>()
+            .init_resource::<TimeScale>()
+            .add_stage_after(
+                CoreStage::Update,
+                FixedUpdateStage,
+                SystemStage::parallel().with_run_criteria(run_fixed_steps),
+            )
+            .add_system_to_stage(
+                FixedUpdateStage,
+                record_translations.exclusive_system().at_start(),
+            )
+            .add_system_to_stage(CoreStage::PreUpdate, restore_translations)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate_translations.before(TransformSystem::TransformPropagate),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(reset_fixed_time)
+                    .with_system(reset_time_scale),
+            );
+    }
+}
+
+/// Runs once per fixed step while in `GameState::Playing`.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+pub struct FixedUpdateStage;
+
+/// Drop-in replacement for `Time` inside `FixedUpdateStage`.
+pub struct FixedTime {
+    step: Duration,
+    /// Steps a single frame may run at most; whatever a slow frame could not
+    /// catch up on is dropped instead of making the next frame even slower.
+    pub max_steps: u32,
+    /// Runs exactly one step per frame, however long the frame took. Makes a
+    /// headless app advance the game one step per `App::update`.
+    pub lockstep: bool,
+    accumulator: Duration,
+    elapsed: Duration,
+    ticks: u64,
+}
+
+impl Default for FixedTime {
+    fn default() -> Self {
+        Self::from_steps_per_second(60.)
+    }
+}
+
+impl FixedTime {
+    pub fn from_steps_per_second(rate: f64) -> Self {
+        Self {
+            step: Duration::from_secs_f64(1. / rate),
+            max_steps: 5,
+            lockstep: false,
+            accumulator: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            ticks: 0,
+        }
+    }
+
+    pub fn with_lockstep(mut self) -> Self {
+        self.lockstep = true;
+        self
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.step
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// Simulated seconds since the current run started.
+    pub fn seconds_since_startup(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+
+    /// Steps run since the current run started.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// How far real time is into the next step, from 0 to 1.
+    pub fn overstep_percentage(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+/// How fast the world runs inside `FixedUpdateStage`, 1 being full speed.
+///
+/// Systems that move the world scale the `FixedTime` deltas by it, entities
+/// with `IgnoresTimeScale` keep going at full speed.
+///
+/// NOTE: only change it at the start of a step, see `update_bullet_time`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeScale(pub f32);
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+impl TimeScale {
+    pub fn delta(&self, time: &FixedTime) -> Duration {
+        time.delta().mul_f32(self.0)
+    }
+
+    pub fn delta_seconds(&self, time: &FixedTime) -> f32 {
+        time.delta_seconds() * self.0
+    }
+
+    /// The step's delta for an entity, unscaled if it ignores the time scale.
+    pub fn delta_for(&self, time: &FixedTime, ignores: Option<&IgnoresTimeScale>) -> Duration {
+        match ignores {
+            Some(_) => time.delta(),
+            None => self.delta(time),
+        }
+    }
+
+    pub fn delta_seconds_for(&self, time: &FixedTime, ignores: Option<&IgnoresTimeScale>) -> f32 {
+        match ignores {
+            Some(_) => time.delta_seconds(),
+            None => self.delta_seconds(time),
+        }
+    }
+}
+
+/// Keeps an entity at full speed while the `TimeScale` slows the world, e.g.
+/// the player and its abilities' cooldowns.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct IgnoresTimeScale;
+
+/// Stage criteria of `FixedUpdateStage`; `steps` counts the steps run this
+/// frame so far.
+fn run_fixed_steps(
+    mut steps: Local<u32>,
+    state: Res<State<GameState>>,
+    time: Res<Time>,
+    mut fixed_time: ResMut<FixedTime>,
+) -> ShouldRun {
+    if *state.current() != GameState::Playing {
+        return ShouldRun::No;
+    }
+    // NOTE: called again after every step, only add the frame time once
+    if *steps == 0 {
+        let frame_time = if fixed_time.lockstep {
+            fixed_time.step
+        } else {
+            time.delta()
+        };
+        fixed_time.accumulator += frame_time;
+    }
+
+    let step = fixed_time.step;
+    if fixed_time.accumulator >= step && *steps < fixed_time.max_steps {
+        fixed_time.accumulator -= step;
+        fixed_time.elapsed += step;
+        fixed_time.ticks += 1;
+        *steps += 1;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        if fixed_time.accumulator >= step {
+            fixed_time.accumulator = Duration::ZERO;
+        }
+        *steps = 0;
+        ShouldRun::No
+    }
+}
+
+fn reset_fixed_time(mut fixed_time: ResMut<FixedTime>) {
+    fixed_time.accumulator = Duration::ZERO;
+    fixed_time.elapsed = Duration::ZERO;
+    fixed_time.ticks = 0;
+}
+
+fn reset_time_scale(mut time_scale: ResMut<TimeScale>) {
+    *time_scale = TimeScale::default();
+}
+
+/// Simulated translation of a moving entity before and after the last step.
+/// Its `Transform` only holds the simulated one during `FixedUpdateStage`.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+}
+
+type NewlyMovingQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static Transform), (With<Velocity>, Without<Interpolated>)>;
+
+/// Remembers where everything was before this step. Moving entities pick up
+/// an `Interpolated` on their first step.
+fn record_translations(
+    mut commands: Commands,
+    mut q_interpolated: Query<(&Transform, &mut Interpolated)>,
+    q_new: NewlyMovingQuery,
+) {
+    for (transform, mut interpolated) in q_interpolated.iter_mut() {
+        interpolated.previous = transform.translation;
+    }
+    for (entity, transform) in q_new.iter() {
+        commands.entity(entity).insert(Interpolated {
+            previous: transform.translation,
+            current: transform.translation,
+        });
+    }
+}
+
+/// Puts the simulated translations back before the next steps run.
+fn restore_translations(mut query: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in query.iter_mut() {
+        transform.translation = interpolated.current;
+    }
+}
+
+fn interpolate_translations(
+    fixed_time: Res<FixedTime>,
+    mut query: Query<(&mut Transform, &mut Interpolated)>,
+) {
+    // NOTE: in lockstep every frame ends exactly on a step
+    let alpha = if fixed_time.lockstep {
+        1.
+    } else {
+        fixed_time.overstep_percentage()
+    };
+    for (mut transform, mut interpolated) in query.iter_mut() {
+        interpolated.current = transform.translation;
+        transform.translation = interpolated.previous.lerp(interpolated.current, alpha);
+    }
+}
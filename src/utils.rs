@@ -1,17 +1,11 @@
+use crate::arena::Cursor;
 use bevy::prelude::*;
 
 pub fn get_angle_between_transform_and_cursor(
-    window: &Window,
+    cursor: &Cursor,
     player_transform: &Transform,
 ) -> Option<f32> {
-    if let Some(pos) = window.cursor_position() {
-        // get the size of the window
-        let size = Vec2::new(window.width() as f32, window.height() as f32);
-
-        // the default orthographic projection is in pixels from the center;
-        // just undo the translation
-        let cursor_position = pos - size / 2.0;
-
+    if let Some(cursor_position) = cursor.position {
         let direction = cursor_position - player_transform.translation.truncate();
         let angle = Vec2::X.angle_between(direction);
         if angle.is_nan() {
@@ -22,3 +16,87 @@ pub fn get_angle_between_transform_and_cursor(
         None
     }
 }
+
+/// Angle at which a bullet fired from `shooter` at `bullet_speed` meets a
+/// target moving in a straight line, or `None` if it can never catch up.
+pub fn intercept_angle(
+    shooter: Vec2,
+    target: Vec2,
+    target_velocity: Vec2,
+    bullet_speed: f32,
+) -> Option<f32> {
+    // Solve |relative + target_velocity * t| = bullet_speed * t for the
+    // smallest positive t
+    let relative = target - shooter;
+    let a = target_velocity.length_squared() - bullet_speed.powi(2);
+    let b = 2. * relative.dot(target_velocity);
+    let c = relative.length_squared();
+
+    let time = if a.abs() < f32::EPSILON {
+        if b.abs() < f32::EPSILON {
+            return None;
+        }
+        -c / b
+    } else {
+        let discriminant = b.powi(2) - 4. * a * c;
+        if discriminant < 0. {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (t1, t2) = ((-b - root) / (2. * a), (-b + root) / (2. * a));
+        match (t1 > 0., t2 > 0.) {
+            (true, true) => t1.min(t2),
+            (true, false) => t1,
+            (false, true) => t2,
+            (false, false) => return None,
+        }
+    };
+    if time <= 0. {
+        return None;
+    }
+
+    let angle = Vec2::X.angle_between(relative + target_velocity * time);
+    if angle.is_nan() {
+        return None;
+    }
+    Some(angle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const TOLERANCE: f32 = 1e-4;
+
+    #[test]
+    fn aims_straight_at_a_stationary_target() {
+        let angle = intercept_angle(Vec2::ZERO, Vec2::new(100., 100.), Vec2::ZERO, 50.);
+        assert!((angle.unwrap() - PI / 4.).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn leads_a_target_crossing_the_line_of_fire() {
+        let target = Vec2::new(100., 0.);
+        let target_velocity = Vec2::new(0., 50.);
+        let angle = intercept_angle(Vec2::ZERO, target, target_velocity, 100.).unwrap();
+        // NOTE: |(100, 50t)| = 100t meets at t = 2 / sqrt(3), 30 degrees up
+        assert!((angle - PI / 6.).abs() < TOLERANCE);
+
+        let time = 2. / 3f32.sqrt();
+        let bullet = 100. * time * Vec2::new(angle.cos(), angle.sin());
+        assert!(bullet.distance(target + target_velocity * time) < 1e-2);
+    }
+
+    #[test]
+    fn gives_up_on_a_target_it_can_not_catch() {
+        let angle = intercept_angle(Vec2::ZERO, Vec2::new(100., 0.), Vec2::new(200., 0.), 100.);
+        assert_eq!(angle, None);
+    }
+
+    #[test]
+    fn catches_a_target_as_fast_as_the_bullet_coming_closer() {
+        let angle = intercept_angle(Vec2::ZERO, Vec2::new(100., 0.), Vec2::new(-100., 0.), 100.);
+        assert!(angle.unwrap().abs() < TOLERANCE);
+    }
+}
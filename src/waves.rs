@@ -0,0 +1,115 @@
+use crate::enemy::{Enemy, EnemySpawner};
+use crate::enemy_archetype::EnemyKind;
+use crate::game::GameState;
+use crate::timestep::{FixedTime, FixedUpdateStage, TimeScale};
+use bevy::prelude::*;
+
+const WAVE_REPEAT_OFFSET: f32 = 60.;
+
+/// Spawns the waves of the `WaveScript` one after the other, each one once
+/// the previous one has been cleared.
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WaveScript>()
+            .init_resource::<WaveProgress>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reset_waves))
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::new().with_system(run_waves.label("waves").after("collision")),
+            );
+    }
+}
+
+#[derive(Debug)]
+pub struct Wave {
+    /// Seconds to wait once the previous wave is cleared
+    pub delay: f32,
+    pub spawns: Vec<(EnemyKind, Vec2)>,
+}
+
+/// The waves of a run, in order. The boss shows up after the last one.
+pub struct WaveScript {
+    pub waves: Vec<Wave>,
+}
+
+impl Default for WaveScript {
+    fn default() -> Self {
+        Self {
+            waves: vec![
+                Wave {
+                    delay: 0.,
+                    spawns: vec![
+                        (EnemyKind::Shooter, Vec2::new(350., 0.)),
+                        (EnemyKind::Turret, Vec2::new(450., 220.)),
+                        (EnemyKind::Splitter, Vec2::new(450., -220.)),
+                    ],
+                },
+                Wave {
+                    delay: 2.,
+                    spawns: vec![
+                        (EnemyKind::Charger, Vec2::new(500., 150.)),
+                        (EnemyKind::Charger, Vec2::new(500., -150.)),
+                        (EnemyKind::Swarmer, Vec2::new(0., 300.)),
+                        (EnemyKind::Swarmer, Vec2::new(50., 300.)),
+                        (EnemyKind::Swarmer, Vec2::new(0., -300.)),
+                        (EnemyKind::Swarmer, Vec2::new(50., -300.)),
+                    ],
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WaveProgress {
+    next: usize,
+    waited: f32,
+}
+
+impl WaveProgress {
+    /// Whether every wave of the script has been spawned.
+    pub fn finished(&self, script: &WaveScript) -> bool {
+        self.next >= script.waves.len()
+    }
+
+    pub fn waves_spawned(&self) -> usize {
+        self.next
+    }
+}
+
+fn reset_waves(mut progress: ResMut<WaveProgress>) {
+    *progress = WaveProgress::default();
+}
+
+fn run_waves(
+    mut spawner: EnemySpawner,
+    time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    script: Res<WaveScript>,
+    mut progress: ResMut<WaveProgress>,
+    q_enemy: Query<(), With<Enemy>>,
+) {
+    if !q_enemy.is_empty() {
+        return;
+    }
+    let wave = match script.waves.get(progress.next) {
+        Some(wave) => wave,
+        None => return,
+    };
+    progress.waited += time_scale.delta_seconds(&time);
+    // NOTE: the first wave of a run shows up on its first step
+    if progress.waited < wave.delay {
+        return;
+    }
+    // NOTE: bigger waves go through the spawns again, a bit further out each time
+    let count = spawner.difficulty.wave_size(wave.spawns.len());
+    for (i, &(kind, position)) in wave.spawns.iter().cycle().take(count).enumerate() {
+        let round = (i / wave.spawns.len()) as f32;
+        let position = position + round * WAVE_REPEAT_OFFSET * position.normalize_or_zero();
+        spawner.spawn(kind, position);
+    }
+    progress.next += 1;
+    progress.waited = 0.;
+}
@@ -0,0 +1,627 @@
+use bevy::app::{Events, ManualEventReader};
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use gameing::arena::Cursor;
+use gameing::boss::{Boss, BossEncounter};
+use gameing::bullet::{BulletAttributes, BulletBehaviour, BulletCancelled};
+use gameing::config::GameConfig;
+use gameing::difficulty::{Difficulty, DifficultySettings};
+use gameing::enemy::{Enemy, EnemyBullet};
+use gameing::enemy_archetype::{EnemyArchetypes, EnemyKind};
+use gameing::game::{GameState, Speed};
+use gameing::pickup::{ActiveBuffs, Pickup, PickupKind};
+use gameing::player::{Player, PlayerBullet};
+use gameing::replay::{data_hash, Replay, ReplayMode};
+use gameing::rng::GameRng;
+use gameing::timestep::TimeScale;
+use gameing::waves::{Wave, WaveScript};
+use gameing::{HeadlessPlugin, WeaponKind};
+use leafwing_input_manager::MockInput;
+
+/// Live input and the same seed for every run.
+fn headless_app() -> App {
+    let mut app = headless_app_with(ReplayMode::Live);
+    app.world.get_resource_mut::<GameRng>().unwrap().fixed_seed = Some(1);
+    // NOTE: the first update enters `GameState::Playing` and spawns everything
+    app.update();
+    app
+}
+
+fn headless_app_with(replay_mode: ReplayMode) -> App {
+    let mut app = App::new();
+    app.insert_resource(replay_mode)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(HeadlessPlugin);
+    app
+}
+
+/// Live input and a single wave with `spawns`, coming right away.
+fn headless_app_with_wave(spawns: Vec<(EnemyKind, Vec2)>) -> App {
+    let mut app = headless_app_with(ReplayMode::Live);
+    app.insert_resource(WaveScript {
+        waves: vec![Wave { delay: 0., spawns }],
+    });
+    app
+}
+
+/// Runs `frames` updates, one fixed step each.
+fn run_frames(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+/// Switches from the pistol the run starts with to `weapon`, one press of
+/// next weapon at a time.
+fn equip(app: &mut App, weapon: WeaponKind) {
+    let presses = WeaponKind::ALL
+        .iter()
+        .position(|&kind| kind == weapon)
+        .unwrap();
+    for _ in 0..presses {
+        app.send_input(KeyCode::E);
+        run_frames(app, 1);
+        app.reset_inputs();
+        run_frames(app, 1);
+    }
+}
+
+fn enemy_kinds(app: &mut App) -> Vec<EnemyKind> {
+    app.world
+        .query_filtered::<&EnemyKind, With<Enemy>>()
+        .iter(&app.world)
+        .copied()
+        .collect()
+}
+
+fn player_position(app: &mut App) -> Vec3 {
+    app.world
+        .query_filtered::<&Transform, With<Player>>()
+        .iter(&app.world)
+        .next()
+        .unwrap()
+        .translation
+}
+
+#[test]
+fn spawns_player_and_enemy() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+
+    assert_eq!(app.world.query::<&Player>().iter(&app.world).count(), 1);
+    assert!(app.world.query::<&Enemy>().iter(&app.world).count() > 0);
+}
+
+#[test]
+fn held_key_moves_player() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+    let start = player_position(&mut app);
+
+    app.send_input(KeyCode::D);
+    run_frames(&mut app, 10);
+
+    let end = player_position(&mut app);
+    assert!(end.x > start.x, "{:?} -> {:?}", start, end);
+    assert!((end.y - start.y).abs() < f32::EPSILON);
+}
+
+#[test]
+fn clicking_shoots_at_the_cursor() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(0., 0.));
+    app.send_input(MouseButton::Left);
+    run_frames(&mut app, 2);
+
+    assert!(app.world.query::<&PlayerBullet>().iter(&app.world).count() > 0);
+}
+
+#[test]
+fn player_bullets_destroy_enemies() {
+    let mut app = headless_app_with_wave(vec![(EnemyKind::Shooter, Vec2::new(-250., 0.))]);
+    run_frames(&mut app, 2);
+    assert_eq!(app.world.query::<&Enemy>().iter(&app.world).count(), 1);
+
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(-250., 0.));
+    tap_shoot(&mut app);
+    run_frames(&mut app, 30);
+
+    assert!(!enemy_kinds(&mut app).contains(&EnemyKind::Shooter));
+}
+
+#[test]
+fn next_weapon_switches_to_the_shotgun() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+
+    // NOTE: held down, it still only switches once
+    app.send_input(KeyCode::E);
+    run_frames(&mut app, 3);
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(0., 0.));
+    app.send_input(MouseButton::Left);
+    run_frames(&mut app, 1);
+
+    assert_eq!(
+        app.world.query::<&PlayerBullet>().iter(&app.world).count(),
+        6
+    );
+}
+
+#[test]
+fn charged_shot_fires_when_let_go() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+    equip(&mut app, WeaponKind::ChargedShot);
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(0., 0.));
+    app.send_input(MouseButton::Left);
+    run_frames(&mut app, 120);
+    assert_eq!(
+        app.world.query::<&PlayerBullet>().iter(&app.world).count(),
+        0
+    );
+
+    app.reset_inputs();
+    run_frames(&mut app, 1);
+
+    // NOTE: held long enough for a full charge, which doubles the speed
+    let speeds: Vec<f32> = app
+        .world
+        .query_filtered::<&BulletAttributes, With<PlayerBullet>>()
+        .iter(&app.world)
+        .map(|attributes| attributes.speed)
+        .collect();
+    assert_eq!(speeds, vec![250. * 0.7 * 2.]);
+}
+
+fn player_bullet_speeds(app: &mut App) -> Vec<f32> {
+    let mut speeds: Vec<f32> = app
+        .world
+        .query_filtered::<&BulletAttributes, With<PlayerBullet>>()
+        .iter(&app.world)
+        .map(|attributes| attributes.speed)
+        .collect();
+    speeds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    speeds
+}
+
+/// Presses and lets go of shoot right away.
+fn tap_shoot(app: &mut App) {
+    app.send_input(MouseButton::Left);
+    run_frames(app, 1);
+    app.reset_inputs();
+    run_frames(app, 1);
+}
+
+#[test]
+fn charged_shot_waits_for_the_cooldown() {
+    let mut app = headless_app_with(ReplayMode::Live);
+    app.insert_resource(WaveScript { waves: vec![] });
+    run_frames(&mut app, 1);
+    equip(&mut app, WeaponKind::ChargedShot);
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(0., 100.));
+    tap_shoot(&mut app);
+    assert_eq!(player_bullet_speeds(&mut app).len(), 1);
+
+    // NOTE: let go during the cooldown, fired once it is over
+    tap_shoot(&mut app);
+    assert_eq!(player_bullet_speeds(&mut app).len(), 1);
+    run_frames(&mut app, 30);
+    assert_eq!(player_bullet_speeds(&mut app).len(), 2);
+
+    // NOTE: held for a second right after a shot, the 0.3 of the cooldown
+    // do not count
+    run_frames(&mut app, 30);
+    tap_shoot(&mut app);
+    app.send_input(MouseButton::Left);
+    run_frames(&mut app, 60);
+    app.reset_inputs();
+    run_frames(&mut app, 1);
+    let speeds = player_bullet_speeds(&mut app);
+    assert_eq!(speeds.len(), 4);
+    let charge = speeds[3] / (250. * 0.7) - 1.;
+    assert!((charge - 0.7 / 1.5).abs() < 0.05, "charged {}", charge);
+}
+
+#[test]
+fn charged_shots_erase_enemy_bullets() {
+    let mut app = headless_app_with_wave(vec![(EnemyKind::Turret, Vec2::new(150., 0.))]);
+    run_frames(&mut app, 2);
+    equip(&mut app, WeaponKind::ChargedShot);
+
+    wait_for_enemy_bullet(&mut app);
+    let position = player_position(&mut app);
+    let target = app
+        .world
+        .query_filtered::<&Transform, With<EnemyBullet>>()
+        .iter(&app.world)
+        .map(|transform| transform.translation)
+        .min_by(|a, b| {
+            a.distance(position)
+                .partial_cmp(&b.distance(position))
+                .unwrap()
+        })
+        .unwrap();
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(target.truncate());
+    app.send_input(MouseButton::Left);
+    run_frames(&mut app, 1);
+    app.reset_inputs();
+
+    let mut reader = ManualEventReader::<BulletCancelled>::default();
+    let mut cancelled = 0;
+    for _ in 0..10 {
+        app.update();
+        let events = app.world.get_resource::<Events<BulletCancelled>>().unwrap();
+        cancelled += reader.iter(events).count();
+    }
+    assert!(cancelled > 0);
+}
+
+#[test]
+fn laser_hits_everything_along_the_aim_at_once() {
+    let mut app = headless_app_with_wave(vec![
+        (EnemyKind::Shooter, Vec2::new(250., 0.)),
+        (EnemyKind::Shooter, Vec2::new(550., 0.)),
+    ]);
+    run_frames(&mut app, 2);
+    equip(&mut app, WeaponKind::Laser);
+    assert_eq!(app.world.query::<&Enemy>().iter(&app.world).count(), 2);
+
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(600., 0.));
+    // NOTE: a bolt would still be on its way to the farther one
+    tap_shoot(&mut app);
+    run_frames(&mut app, 1);
+
+    assert!(!enemy_kinds(&mut app).contains(&EnemyKind::Shooter));
+}
+
+#[test]
+fn every_scroll_notch_switches_weapons() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+
+    // NOTE: several notches in a single frame still count one by one
+    let mut wheel = app.world.get_resource_mut::<Events<MouseWheel>>().unwrap();
+    for y in [1., 1., -1.] {
+        wheel.send(MouseWheel {
+            unit: MouseScrollUnit::Line,
+            x: 0.,
+            y,
+        });
+    }
+    run_frames(&mut app, 1);
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(0., 0.));
+    app.send_input(MouseButton::Left);
+    run_frames(&mut app, 1);
+
+    assert_eq!(
+        app.world.query::<&PlayerBullet>().iter(&app.world).count(),
+        6
+    );
+}
+
+#[test]
+fn boss_shows_up_after_the_last_wave() {
+    let mut app = headless_app_with_wave(vec![(EnemyKind::Shooter, Vec2::new(350., 0.))]);
+    run_frames(&mut app, 2);
+    let enemies: Vec<Entity> = app
+        .world
+        .query_filtered::<Entity, With<Enemy>>()
+        .iter(&app.world)
+        .collect();
+    assert_eq!(enemies.len(), 1);
+    for enemy in enemies {
+        app.world.despawn(enemy);
+    }
+    run_frames(&mut app, 2);
+
+    assert_eq!(enemy_kinds(&mut app), vec![EnemyKind::Boss]);
+}
+
+#[test]
+fn defeating_the_boss_wins_the_run() {
+    let mut app = headless_app_with(ReplayMode::Live);
+    app.insert_resource(WaveScript { waves: Vec::new() });
+    // NOTE: one hit is enough, and the boss' bullets can not end the run first
+    let mut archetypes = app.world.get_resource_mut::<EnemyArchetypes>().unwrap();
+    let mut boss = archetypes.get(EnemyKind::Boss).clone();
+    boss.health = 1;
+    archetypes.set(EnemyKind::Boss, boss);
+    app.world
+        .get_resource_mut::<GameConfig>()
+        .unwrap()
+        .player_health = 1000;
+
+    for _ in 0..600 {
+        let boss = app
+            .world
+            .query_filtered::<&Transform, With<Boss>>()
+            .iter(&app.world)
+            .next()
+            .map(|transform| transform.translation.truncate());
+        app.world.get_resource_mut::<Cursor>().unwrap().position = boss;
+        tap_shoot(&mut app);
+        let state = app.world.get_resource::<State<GameState>>().unwrap();
+        if *state.current() == GameState::GameOver {
+            break;
+        }
+    }
+
+    let state = app.world.get_resource::<State<GameState>>().unwrap();
+    assert_eq!(*state.current(), GameState::GameOver);
+    let encounter = app.world.get_resource::<BossEncounter>().unwrap();
+    assert_eq!(*encounter, BossEncounter::Defeated);
+}
+
+#[test]
+fn boss_bullets_orbit_the_boss() {
+    let mut app = headless_app_with(ReplayMode::Live);
+    app.insert_resource(WaveScript { waves: Vec::new() });
+    // NOTE: one hit takes the boss down to its orbiting phase
+    let mut archetypes = app.world.get_resource_mut::<EnemyArchetypes>().unwrap();
+    let mut boss = archetypes.get(EnemyKind::Boss).clone();
+    boss.health = 5;
+    archetypes.set(EnemyKind::Boss, boss);
+    app.world
+        .get_resource_mut::<GameConfig>()
+        .unwrap()
+        .player_health = 1000;
+
+    let orbiting = |app: &mut App| {
+        app.world
+            .query::<(&Transform, &BulletBehaviour)>()
+            .iter(&app.world)
+            .filter_map(|(transform, behaviour)| match *behaviour {
+                BulletBehaviour::Orbit { center, radius, .. } => {
+                    Some((transform.translation.truncate(), center, radius))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    for _ in 0..600 {
+        if !orbiting(&mut app).is_empty() {
+            break;
+        }
+        let boss = app
+            .world
+            .query_filtered::<&Transform, With<Boss>>()
+            .iter(&app.world)
+            .next()
+            .map(|transform| transform.translation.truncate());
+        app.world.get_resource_mut::<Cursor>().unwrap().position = boss;
+        tap_shoot(&mut app);
+    }
+    run_frames(&mut app, 10);
+
+    let (boss, boss_position) = app
+        .world
+        .query_filtered::<(Entity, &Transform), With<Boss>>()
+        .iter(&app.world)
+        .next()
+        .map(|(entity, transform)| (entity, transform.translation.truncate()))
+        .unwrap();
+    let bullets = orbiting(&mut app);
+    assert!(!bullets.is_empty());
+    for (position, center, radius) in bullets {
+        assert_eq!(center, boss);
+        assert!((position.distance(boss_position) - radius).abs() < 5.);
+    }
+}
+
+#[test]
+fn harder_presets_spawn_bigger_waves() {
+    let mut app = headless_app_with(ReplayMode::Live);
+    app.insert_resource(DifficultySettings {
+        preset: Difficulty::Nightmare,
+        dynamic: false,
+    })
+    .insert_resource(WaveScript {
+        waves: vec![Wave {
+            delay: 0.,
+            spawns: vec![
+                (EnemyKind::Turret, Vec2::new(350., 100.)),
+                (EnemyKind::Turret, Vec2::new(350., -100.)),
+            ],
+        }],
+    });
+    run_frames(&mut app, 2);
+
+    assert_eq!(app.world.query::<&Enemy>().iter(&app.world).count(), 3);
+}
+
+#[test]
+fn bomb_destroys_the_enemies_in_reach() {
+    let mut app = headless_app_with_wave(vec![
+        (EnemyKind::Charger, Vec2::new(-200., 0.)),
+        (EnemyKind::Turret, Vec2::new(350., 0.)),
+    ]);
+    run_frames(&mut app, 2);
+    assert_eq!(app.world.query::<&Enemy>().iter(&app.world).count(), 2);
+
+    app.send_input(KeyCode::F);
+    run_frames(&mut app, 2);
+
+    assert_eq!(enemy_kinds(&mut app), vec![EnemyKind::Turret]);
+}
+
+#[test]
+fn splitter_killed_by_a_bomb_still_splits() {
+    let mut app = headless_app_with_wave(vec![(EnemyKind::Splitter, Vec2::new(-200., 0.))]);
+    run_frames(&mut app, 2);
+
+    app.send_input(KeyCode::F);
+    run_frames(&mut app, 2);
+
+    assert_eq!(enemy_kinds(&mut app), vec![EnemyKind::Swarmer; 3]);
+}
+
+#[test]
+fn bullet_time_slows_the_world_until_the_meter_runs_out() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+
+    app.send_input(KeyCode::LShift);
+    run_frames(&mut app, 1);
+    app.reset_inputs();
+    run_frames(&mut app, 2);
+    let time_scale = *app.world.get_resource::<TimeScale>().unwrap();
+    assert!(time_scale.0 < 1.);
+
+    // NOTE: a full meter lasts 3 seconds of 60 steps each
+    run_frames(&mut app, 200);
+    let time_scale = *app.world.get_resource::<TimeScale>().unwrap();
+    assert_eq!(time_scale, TimeScale(1.));
+}
+
+/// Bullets of one side within `reach` of the player.
+fn bullets_near_player<T: Component>(app: &mut App, reach: f32) -> usize {
+    let position = player_position(app);
+    app.world
+        .query_filtered::<&Transform, With<T>>()
+        .iter(&app.world)
+        .filter(|transform| transform.translation.distance(position) < reach)
+        .count()
+}
+
+/// Runs updates until an enemy bullet is about to touch the shield.
+fn wait_for_enemy_bullet(app: &mut App) {
+    for _ in 0..300 {
+        if bullets_near_player::<EnemyBullet>(app, 70.) > 0 {
+            return;
+        }
+        app.update();
+    }
+    panic!("no enemy bullet came close");
+}
+
+#[test]
+fn shield_parries_then_blocks_enemy_bullets() {
+    let mut app = headless_app_with_wave(vec![(EnemyKind::Turret, Vec2::new(100., 0.))]);
+    // NOTE: long enough to block the turret's next burst too
+    app.world
+        .get_resource_mut::<GameConfig>()
+        .unwrap()
+        .block_duration = 10.;
+    run_frames(&mut app, 2);
+
+    wait_for_enemy_bullet(&mut app);
+    app.send_input(MouseButton::Right);
+    let mut parried = false;
+    for _ in 0..10 {
+        app.update();
+        parried |= bullets_near_player::<PlayerBullet>(&mut app, 70.) > 0;
+    }
+    assert!(parried);
+
+    // NOTE: past the parry window, the bullets are only absorbed
+    run_frames(&mut app, 60);
+    wait_for_enemy_bullet(&mut app);
+    for _ in 0..20 {
+        app.update();
+        assert_eq!(bullets_near_player::<PlayerBullet>(&mut app, 70.), 0);
+        assert_eq!(bullets_near_player::<EnemyBullet>(&mut app, 28.), 0);
+    }
+    assert_eq!(app.world.query::<&Player>().iter(&app.world).count(), 1);
+}
+
+#[test]
+fn touching_a_pickup_buffs_the_player() {
+    let mut app = headless_app();
+    run_frames(&mut app, 1);
+    let position = player_position(&mut app);
+    app.world
+        .spawn()
+        .insert(Pickup::new(PickupKind::SpeedBoost))
+        .insert(Transform::from_translation(position));
+    run_frames(&mut app, 2);
+
+    assert_eq!(app.world.query::<&Pickup>().iter(&app.world).count(), 0);
+    let (buffs, speed) = app
+        .world
+        .query_filtered::<(&ActiveBuffs, &Speed), With<Player>>()
+        .iter(&app.world)
+        .next()
+        .unwrap();
+    assert!(buffs.has(PickupKind::SpeedBoost));
+    assert!(speed.0 > 80.);
+}
+
+type GameplayEntities = Or<(With<Player>, With<Enemy>, With<PlayerBullet>)>;
+
+fn gameplay_positions(app: &mut App) -> Vec<Vec3> {
+    let mut positions: Vec<Vec3> = app
+        .world
+        .query_filtered::<&Transform, GameplayEntities>()
+        .iter(&app.world)
+        .map(|transform| transform.translation)
+        .collect();
+    positions.sort_by(|a, b| a.to_array().partial_cmp(&b.to_array()).unwrap());
+    positions
+}
+
+#[test]
+fn same_inputs_play_out_the_same() {
+    let positions = || {
+        let mut app = headless_app();
+        app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(0., 100.));
+        app.send_input(KeyCode::W);
+        app.send_input(MouseButton::Left);
+        run_frames(&mut app, 120);
+        gameplay_positions(&mut app)
+    };
+
+    assert_eq!(positions(), positions());
+}
+
+#[test]
+fn replays_reproduce_the_recorded_run() {
+    let path = std::env::temp_dir().join("gameing_headless_test.replay");
+    let mut app = headless_app_with(ReplayMode::Recording {
+        path: path.clone(),
+        replay: Replay::default(),
+    });
+    // NOTE: the playback has to bring the config along
+    app.world
+        .get_resource_mut::<GameConfig>()
+        .unwrap()
+        .player_speed = 120.;
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(0., 100.));
+    app.send_input(KeyCode::W);
+    app.send_input(MouseButton::Left);
+    run_frames(&mut app, 40);
+    app.reset_inputs();
+    app.world.get_resource_mut::<Cursor>().unwrap().position = Some(Vec2::new(200., -50.));
+    app.send_input(KeyCode::D);
+    app.send_input(KeyCode::Space);
+    run_frames(&mut app, 40);
+    let recorded_positions = gameplay_positions(&mut app);
+
+    let replay = match app.world.get_resource::<ReplayMode>().unwrap() {
+        ReplayMode::Recording { replay, .. } => replay.clone(),
+        _ => unreachable!(),
+    };
+    replay.save(&path).unwrap();
+    let loaded = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, replay);
+
+    let mut app = headless_app_with(ReplayMode::playback(loaded));
+    run_frames(&mut app, 80);
+    assert_eq!(gameplay_positions(&mut app), recorded_positions);
+}
+
+#[test]
+fn replays_of_other_enemy_data_are_refused() {
+    let replay = Replay {
+        data_hash: data_hash(&EnemyArchetypes::default(), &WaveScript::default()) + 1,
+        ..Replay::default()
+    };
+    let mut app = headless_app_with(ReplayMode::playback(replay));
+    run_frames(&mut app, 2);
+    let state = app.world.get_resource::<State<GameState>>().unwrap();
+    assert_eq!(*state.current(), GameState::Menu);
+    let mode = app.world.get_resource::<ReplayMode>().unwrap();
+    assert!(matches!(mode, ReplayMode::Live));
+}